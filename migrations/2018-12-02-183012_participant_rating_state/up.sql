@@ -0,0 +1,2 @@
+ALTER TABLE participant ADD COLUMN deviation DOUBLE NOT NULL DEFAULT 350.0;
+ALTER TABLE participant ADD COLUMN volatility DOUBLE NOT NULL DEFAULT 0.06;
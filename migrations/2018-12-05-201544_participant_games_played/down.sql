@@ -0,0 +1,18 @@
+ALTER TABLE participant RENAME TO temp_participant;
+
+CREATE TABLE participant (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  game_id INTEGER NOT NULL,
+  deck_id INTEGER NOT NULL,
+  win BOOLEAN NOT NULL DEFAULT 0,
+  elo DOUBLE NOT NULL,
+  deviation DOUBLE NOT NULL DEFAULT 350.0,
+  volatility DOUBLE NOT NULL DEFAULT 0.06,
+  CONSTRAINT participant_game_fk FOREIGN KEY(game_id) REFERENCES game(id),
+  CONSTRAINT participant_deck_fk FOREIGN KEY(deck_id) REFERENCES deck(id)
+);
+
+INSERT INTO participant
+  SELECT id, game_id, deck_id, win, elo, deviation, volatility FROM temp_participant;
+
+DROP TABLE temp_participant;
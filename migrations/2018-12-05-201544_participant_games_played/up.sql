@@ -0,0 +1,6 @@
+ALTER TABLE participant ADD COLUMN games_played INTEGER NOT NULL DEFAULT 0;
+
+UPDATE participant SET games_played = (
+  SELECT COUNT(*) FROM participant AS previous
+  WHERE previous.deck_id = participant.deck_id AND previous.game_id < participant.game_id
+);
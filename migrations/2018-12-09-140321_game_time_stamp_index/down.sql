@@ -0,0 +1 @@
+DROP INDEX game_time_stamp_idx;
@@ -0,0 +1 @@
+CREATE INDEX game_time_stamp_idx ON game(time_stamp, id);
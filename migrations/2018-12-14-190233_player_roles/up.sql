@@ -0,0 +1 @@
+ALTER TABLE player ADD COLUMN role TEXT NOT NULL DEFAULT 'member';
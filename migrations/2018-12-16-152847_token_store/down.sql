@@ -0,0 +1,14 @@
+DROP INDEX token_content_idx;
+
+ALTER TABLE token RENAME TO temp_token;
+
+CREATE TABLE token (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  player_id INTEGER NOT NULL,
+  content TEXT NOT NULL,
+  CONSTRAINT participant_player_fk FOREIGN KEY(player_id) REFERENCES player(id)
+);
+
+INSERT INTO token SELECT id, player_id, content FROM temp_token;
+
+DROP TABLE temp_token;
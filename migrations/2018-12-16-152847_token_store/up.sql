@@ -0,0 +1,2 @@
+ALTER TABLE token ADD COLUMN expires_at BIGINT NOT NULL DEFAULT 0;
+CREATE UNIQUE INDEX token_content_idx ON token(content);
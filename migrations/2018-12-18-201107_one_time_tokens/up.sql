@@ -0,0 +1,9 @@
+CREATE TABLE one_time_token (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  player_id INTEGER NOT NULL,
+  purpose TEXT NOT NULL,
+  selector TEXT NOT NULL UNIQUE,
+  verifier TEXT NOT NULL,
+  expires_at BIGINT NOT NULL,
+  CONSTRAINT one_time_token_player_fk FOREIGN KEY(player_id) REFERENCES player(id)
+);
@@ -0,0 +1,12 @@
+-- Rows used to be access tokens, they now represent sessions and old access tokens can't be refreshed
+DELETE FROM token;
+
+CREATE TABLE refresh_token (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  token_id INTEGER NOT NULL,
+  selector TEXT NOT NULL UNIQUE,
+  verifier TEXT NOT NULL,
+  used BOOLEAN NOT NULL DEFAULT 0,
+  expires_at BIGINT NOT NULL,
+  CONSTRAINT refresh_token_token_fk FOREIGN KEY(token_id) REFERENCES token(id)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE api_key (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  player_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  selector TEXT NOT NULL UNIQUE,
+  verifier TEXT NOT NULL,
+  scopes TEXT NOT NULL,
+  created_at BIGINT NOT NULL,
+  last_used_at BIGINT,
+  CONSTRAINT api_key_player_fk FOREIGN KEY(player_id) REFERENCES player(id)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE player ADD COLUMN verified BOOLEAN NOT NULL DEFAULT 0;
+
+-- Players from before verification existed are trusted
+UPDATE player SET verified = 1;
@@ -0,0 +1,16 @@
+DROP TABLE invite;
+
+ALTER TABLE player RENAME TO temp_player;
+
+CREATE TABLE player (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  alias TEXT NOT NULL UNIQUE,
+  email TEXT NOT NULL UNIQUE,
+  password TEXT NOT NULL,
+  role TEXT NOT NULL DEFAULT 'member',
+  verified BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO player SELECT id, alias, email, password, role, verified FROM temp_player;
+
+DROP TABLE temp_player;
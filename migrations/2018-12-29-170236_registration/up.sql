@@ -0,0 +1,11 @@
+ALTER TABLE player ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+
+CREATE TABLE invite (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  code TEXT NOT NULL UNIQUE,
+  created_by INTEGER NOT NULL,
+  expires_at BIGINT NOT NULL,
+  used_by INTEGER,
+  CONSTRAINT invite_created_by_fk FOREIGN KEY(created_by) REFERENCES player(id),
+  CONSTRAINT invite_used_by_fk FOREIGN KEY(used_by) REFERENCES player(id)
+);
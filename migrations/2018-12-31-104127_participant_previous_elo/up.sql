@@ -0,0 +1,13 @@
+ALTER TABLE participant ADD COLUMN previous_elo DOUBLE NOT NULL DEFAULT 1000.0;
+
+-- The rating each deck went into the game with, which is the rating after its previous game
+UPDATE participant SET previous_elo = COALESCE((
+  SELECT previous.elo FROM participant AS previous
+  INNER JOIN game AS previous_game ON previous_game.id = previous.game_id
+  INNER JOIN game AS current_game ON current_game.id = participant.game_id
+  WHERE previous.deck_id = participant.deck_id
+    AND (previous_game.time_stamp < current_game.time_stamp
+      OR (previous_game.time_stamp = current_game.time_stamp AND previous_game.id < current_game.id))
+  ORDER BY previous_game.time_stamp DESC, previous_game.id DESC
+  LIMIT 1
+), 1000.0);
@@ -0,0 +1,2 @@
+DROP TABLE season_standing;
+DROP TABLE season;
@@ -0,0 +1,20 @@
+CREATE TABLE season (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  starts_at BIGINT NOT NULL,
+  ends_at BIGINT NOT NULL,
+  reset DOUBLE NOT NULL DEFAULT 0.0,
+  closed BOOLEAN NOT NULL DEFAULT 0
+);
+
+CREATE TABLE season_standing (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  season_id INTEGER NOT NULL,
+  deck_id INTEGER NOT NULL,
+  rank INTEGER NOT NULL,
+  elo DOUBLE NOT NULL,
+  games INTEGER NOT NULL,
+  wins INTEGER NOT NULL,
+  CONSTRAINT season_standing_season_fk FOREIGN KEY(season_id) REFERENCES season(id),
+  CONSTRAINT season_standing_deck_fk FOREIGN KEY(deck_id) REFERENCES deck(id)
+);
@@ -0,0 +1,19 @@
+DROP INDEX game_league_idx;
+DROP INDEX game_time_stamp_idx;
+
+ALTER TABLE game RENAME TO temp_game;
+
+CREATE TABLE game (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  time_stamp DOUBLE NOT NULL
+);
+
+INSERT INTO game
+  SELECT id, time_stamp FROM temp_game;
+
+DROP TABLE temp_game;
+
+CREATE INDEX game_time_stamp_idx ON game(time_stamp, id);
+
+DROP TABLE league_member;
+DROP TABLE league;
@@ -0,0 +1,20 @@
+CREATE TABLE league (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE,
+  created_at BIGINT NOT NULL
+);
+
+CREATE TABLE league_member (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  league_id INTEGER NOT NULL,
+  player_id INTEGER NOT NULL,
+  role TEXT NOT NULL DEFAULT 'member',
+  CONSTRAINT league_member_league_fk FOREIGN KEY(league_id) REFERENCES league(id),
+  CONSTRAINT league_member_player_fk FOREIGN KEY(player_id) REFERENCES player(id),
+  CONSTRAINT league_member_unique UNIQUE(league_id, player_id)
+);
+
+-- Games without a league stay in the shared pool they were recorded in
+ALTER TABLE game ADD COLUMN league_id INTEGER REFERENCES league(id);
+
+CREATE INDEX game_league_idx ON game(league_id, time_stamp, id);
@@ -0,0 +1,246 @@
+use api::auth::AdminToken;
+use api::common::{current_time, random_string};
+use api::error::{ApiError, FieldError};
+use api::game::recompute_ratings;
+use api::player::PlayerResponse;
+use api::registration::INVITE_VALIDITY;
+use api::season::{current_standings, SeasonResponse};
+use api::validation::Validator;
+use db::DbConn;
+use diesel::Connection;
+use models::api_key::ApiKey;
+use models::invite::{Invite, NewInvite};
+use models::player::{AccountStatus, Player};
+use models::season::{NewSeason, Season};
+use models::token::Token;
+use rocket_contrib::json::Json;
+
+const INVITE_CODE_LENGTH: usize = 12;
+
+#[derive(Serialize)]
+pub struct RecomputeResponse {
+    updated: usize,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSeasonRequest {
+    name: String,
+    starts_at: i64,
+    ends_at: i64,
+    reset: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct InviteResponse {
+    id: i32,
+    code: String,
+    created_by: i32,
+    expires_at: i64,
+    used_by: Option<i32>,
+}
+
+impl From<Invite> for InviteResponse {
+    fn from(invite: Invite) -> Self {
+        InviteResponse {
+            id: invite.id,
+            code: invite.code,
+            created_by: invite.created_by,
+            expires_at: invite.expires_at,
+            used_by: invite.used_by,
+        }
+    }
+}
+
+#[post("/ratings/recompute")]
+pub fn recompute(conn: DbConn, _token: AdminToken) -> Result<Json<RecomputeResponse>, ApiError> {
+    let updated = recompute_ratings(&conn)?;
+    info!("Recomputed ratings, {} participants changed", updated);
+    Ok(Json(RecomputeResponse { updated }))
+}
+
+impl CreateSeasonRequest {
+    fn validate(&self, existing: &Vec<Season>) -> Result<(), ApiError> {
+        let mut validator = Validator::new();
+        validator.check(!self.name.trim().is_empty(), "name", "must not be blank");
+        validator.check(
+            self.starts_at < self.ends_at,
+            "ends_at",
+            "must be after starts_at",
+        );
+        if let Some(reset) = self.reset {
+            validator.check(
+                reset >= 0.0 && reset <= 1.0,
+                "reset",
+                "must be between 0 and 1",
+            );
+        }
+        for season in existing {
+            validator.check(
+                self.ends_at <= season.starts_at || season.ends_at <= self.starts_at,
+                "starts_at",
+                format!("overlaps with {}", season.name),
+            );
+        }
+        validator.finish()
+    }
+}
+
+#[get("/invites")]
+pub fn get_invites(
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<Vec<InviteResponse>>, ApiError> {
+    let invites = Invite::all(&conn)?;
+    Ok(Json(
+        invites.into_iter().map(InviteResponse::from).collect(),
+    ))
+}
+
+#[post("/invites")]
+pub fn create_invite(conn: DbConn, token: AdminToken) -> Result<Json<InviteResponse>, ApiError> {
+    let invite = Invite::insert(
+        NewInvite {
+            code: random_string(INVITE_CODE_LENGTH),
+            created_by: token.0.player_id(),
+            expires_at: current_time() + *INVITE_VALIDITY,
+        },
+        &conn,
+    )?;
+    Ok(Json(InviteResponse::from(invite)))
+}
+
+#[delete("/invites/<id>")]
+pub fn delete_invite(id: i32, conn: DbConn, _token: AdminToken) -> Result<(), ApiError> {
+    let invite = Invite::find_by_id(id, &conn)?;
+    Invite::delete(invite.id, &conn)?;
+    Ok({})
+}
+
+#[get("/players/pending")]
+pub fn get_pending_players(
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<Vec<PlayerResponse>>, ApiError> {
+    let players = Player::find_by_status(AccountStatus::Pending, &conn)?;
+    Ok(Json(
+        players
+            .into_iter()
+            .map(Player::into_player_response)
+            .collect(),
+    ))
+}
+
+#[post("/players/<id>/approve")]
+pub fn approve_player(
+    id: i32,
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<PlayerResponse>, ApiError> {
+    set_status(id, AccountStatus::Active, &conn)
+}
+
+#[post("/players/<id>/reject")]
+pub fn reject_player(
+    id: i32,
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<PlayerResponse>, ApiError> {
+    set_status(id, AccountStatus::Rejected, &conn)
+}
+
+/// Players who aren't approved any more are logged out and lose their API keys
+fn set_status(
+    id: i32,
+    status: AccountStatus,
+    conn: &DbConn,
+) -> Result<Json<PlayerResponse>, ApiError> {
+    let player = conn.transaction::<_, ApiError, _>(|| {
+        let player = Player::find_by_id(id, conn)?;
+        let player = Player::update(
+            Player {
+                status: status.as_str().to_string(),
+                ..player
+            },
+            conn,
+        )?;
+        if status != AccountStatus::Active {
+            Token::delete_by_player(player.id, conn)?;
+            ApiKey::delete_by_player(player.id, conn)?;
+        }
+        Ok(player)
+    })?;
+    Ok(Json(player.into_player_response()))
+}
+
+///
+/// Adding a season changes the ratings of every game played after it
+/// starts, so they are all recomputed.
+///
+#[post("/seasons", format = "application/json", data = "<req>")]
+pub fn create_season(
+    req: Json<CreateSeasonRequest>,
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<SeasonResponse>, ApiError> {
+    let req = req.into_inner();
+
+    // Checked in the transaction so two requests can't both add an overlapping season
+    let season = conn.transaction::<_, ApiError, _>(|| {
+        req.validate(&Season::all(&conn)?)?;
+        let season = Season::insert(
+            NewSeason {
+                name: req.name,
+                starts_at: req.starts_at,
+                ends_at: req.ends_at,
+                reset: req.reset.unwrap_or(0.0),
+            },
+            &conn,
+        )?;
+        recompute_ratings(&conn)?;
+        Ok(season)
+    })?;
+    Ok(Json(SeasonResponse::from(season)))
+}
+
+///
+/// Freezes the standings of a season that has ended. Later changes to
+/// games or ratings no longer affect them.
+///
+#[post("/seasons/<id>/close")]
+pub fn close_season(
+    id: i32,
+    conn: DbConn,
+    _token: AdminToken,
+) -> Result<Json<SeasonResponse>, ApiError> {
+    let season = conn.transaction::<_, ApiError, _>(|| {
+        let season = Season::find_by_id(id, &conn)?;
+        if season.closed {
+            return Err(ApiError::Conflict(vec![FieldError {
+                field: "closed".to_string(),
+                message: "the season is already closed".to_string(),
+            }]));
+        }
+
+        let mut validator = Validator::new();
+        validator.check(
+            season.ends_at <= current_time(),
+            "ends_at",
+            "the season has not ended yet",
+        );
+        validator.finish()?;
+
+        season.close(&current_standings(&season, &conn)?, &conn)?;
+        Ok(Season::find_by_id(id, &conn)?)
+    })?;
+    Ok(Json(SeasonResponse::from(season)))
+}
+
+#[delete("/seasons/<id>")]
+pub fn delete_season(id: i32, conn: DbConn, _token: AdminToken) -> Result<(), ApiError> {
+    conn.transaction::<_, ApiError, _>(|| {
+        let season = Season::find_by_id(id, &conn)?;
+        season.delete(&conn)?;
+        recompute_ratings(&conn)?;
+        Ok({})
+    })
+}
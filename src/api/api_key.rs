@@ -0,0 +1,107 @@
+use api::auth::{MemberToken, API_KEY_PREFIX, SELECTOR_LENGTH, VERIFIER_LENGTH};
+use api::common::{current_time, digest, random_string};
+use api::error::ApiError;
+use api::validation::Validator;
+use db::DbConn;
+use models::api_key::{ApiKey, NewApiKey, Scope};
+use rocket_contrib::json::Json;
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<Scope>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyResponse {
+    id: i32,
+    name: String,
+    scopes: Vec<Scope>,
+    created_at: i64,
+    last_used_at: Option<i64>,
+    /// Only ever sent when the key is created, it can't be recovered later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        ApiKeyResponse {
+            id: api_key.id,
+            scopes: api_key.scopes(),
+            name: api_key.name,
+            created_at: api_key.created_at,
+            last_used_at: api_key.last_used_at,
+            key: None,
+        }
+    }
+}
+
+#[get("/")]
+pub fn get_api_keys(
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<Json<Vec<ApiKeyResponse>>, ApiError> {
+    let api_keys = ApiKey::find_by_player(token.0.player_id(), &conn)?;
+    Ok(Json(
+        api_keys.into_iter().map(ApiKeyResponse::from).collect(),
+    ))
+}
+
+///
+/// Every key can read, `scopes` grants anything beyond that. The key is
+/// only returned here, only a digest of it is stored.
+///
+#[post("/", format = "application/json", data = "<req>")]
+pub fn create_api_key(
+    req: Json<CreateApiKeyRequest>,
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<Json<ApiKeyResponse>, ApiError> {
+    let create_request = req.into_inner();
+    let mut validator = Validator::new();
+    validator.check(
+        !create_request.name.trim().is_empty(),
+        "name",
+        "can't be blank",
+    );
+    validator.finish()?;
+
+    let mut scopes = vec![Scope::Read];
+    for scope in create_request.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    let selector = random_string(SELECTOR_LENGTH);
+    let verifier = random_string(VERIFIER_LENGTH);
+    let api_key = ApiKey::insert(
+        NewApiKey {
+            player_id: token.0.player_id(),
+            name: create_request.name,
+            selector: selector.clone(),
+            verifier: digest(&verifier),
+            scopes: scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            created_at: current_time(),
+        },
+        &conn,
+    )?;
+
+    Ok(Json(ApiKeyResponse {
+        key: Some(format!("{}{}.{}", API_KEY_PREFIX, selector, verifier)),
+        ..ApiKeyResponse::from(api_key)
+    }))
+}
+
+#[delete("/<id>")]
+pub fn delete_api_key(id: i32, conn: DbConn, token: MemberToken) -> Result<(), ApiError> {
+    let api_key = ApiKey::find_by_id(id, &conn)?;
+    token.0.authorize(api_key.player_id)?;
+    ApiKey::delete(api_key.id, &conn)?;
+    Ok({})
+}
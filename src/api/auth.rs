@@ -1,31 +1,56 @@
+use api::common::{current_time, digest, digest_matches, random_string};
 use api::error::ApiError;
-use bcrypt::{hash, verify};
+use api::jwt::KEYSET;
+use api::one_time_token;
+use api::password::PASSWORD_POLICY;
+use api::throttle::{ClientIp, LoginThrottle};
+use api::validation::Validator;
+use api::verification::REQUIRE_EMAIL_VERIFICATION;
+use bcrypt::verify;
+use config;
 use db::DbConn;
-use dotenv::dotenv;
-use jsonwebtoken::errors::Error as JwtError;
-use jsonwebtoken::{decode, Validation};
-use jsonwebtoken::{encode, Header};
-use models::player::Player;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use mailer::{Email, Mailer};
+use models::api_key::{ApiKey, Scope};
+use models::one_time_token::Purpose;
+use models::player::{AccountStatus, Player, Role};
+use models::refresh_token::{NewRefreshToken, RefreshToken};
+use models::token::{NewToken, Token};
 use rocket::http::Status;
 use rocket::request::{self, FromRequest, Request};
 use rocket::Outcome;
+use rocket::State;
 use rocket_contrib::json::Json;
-use std::env;
-use time;
 
-pub const DEFAULT_TOKEN_VALIDITY: i64 = 604800; // 7 days
+pub const API_KEY_PREFIX: &str = "hk_";
+const BEARER: &str = "Bearer ";
+pub const SELECTOR_LENGTH: usize = 16;
+pub const VERIFIER_LENGTH: usize = 32;
+const SESSION_ID_LENGTH: usize = 32;
+/// How stale an API key's `last_used_at` may get before it is written again, in seconds
+const API_KEY_TOUCH_INTERVAL: i64 = 60;
 
 lazy_static! {
-    static ref AUTH_SECRET: String = {
-        dotenv().ok();
-        env::var("AUTH_SECRET").expect("AUTH_SECRET must be set")
-    };
+    /// How long an access token is accepted for, in seconds
+    pub static ref ACCESS_TOKEN_VALIDITY: i64 = config::seconds_or("ACCESS_TOKEN_VALIDITY", 900);
+    /// How long a session lasts without being refreshed, in seconds
+    pub static ref REFRESH_TOKEN_VALIDITY: i64 =
+        config::seconds_or("REFRESH_TOKEN_VALIDITY", 2592000);
+    /// How long a password reset token can be used for, in seconds
+    pub static ref PASSWORD_RESET_VALIDITY: i64 =
+        config::seconds_or("PASSWORD_RESET_VALIDITY", 3600);
+    /// Prepended to the reset token in the email, eg. `https://example.com/reset?token=`
+    pub static ref PASSWORD_RESET_URL: String = config::env_or("PASSWORD_RESET_URL", String::new());
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
     pub id: i32,
     pub exp: i64,
+    pub role: Role,
+    /// The session the token was issued for, see `Token`
+    pub sid: String,
 }
 
 #[derive(Deserialize)]
@@ -36,6 +61,14 @@ pub struct LoginRequest {
 #[derive(Serialize)]
 pub struct LoginResponse {
     token: String,
+    refresh_token: String,
+    /// Seconds until `token` expires
+    expires_in: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
 }
 
 #[derive(Deserialize)]
@@ -45,83 +78,520 @@ pub struct ChangePasswordRequest {
     new_password: String,
 }
 
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    token: String,
+    new_password: String,
+}
+
 pub struct ApiToken {
     player_id: i32,
-    _exp: i64,
-    _raw: String,
+    role: Role,
+    credential: Credential,
+}
+
+/// What a request was authenticated with
+enum Credential {
+    /// An access token, carrying the id of its session
+    Session(String),
+    ApiKey(Vec<Scope>),
 }
 
+/// A token belonging to a player that is allowed to make changes. API keys
+/// are never accepted here, they only get what their scopes allow.
+pub struct MemberToken(pub ApiToken);
+
+/// A token belonging to an admin
+pub struct AdminToken(pub ApiToken);
+
+/// A member's token or an API key with the `submit_games` scope
+pub struct GameSubmitterToken(pub ApiToken);
+
+impl ApiToken {
+    pub fn player_id(&self) -> i32 {
+        self.player_id
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    pub fn is_member(&self) -> bool {
+        self.role == Role::Admin || self.role == Role::Member
+    }
+
+    /// Sessions aren't scoped, only API keys are
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match self.credential {
+            Credential::Session(_) => true,
+            Credential::ApiKey(ref scopes) => scopes.contains(&scope),
+        }
+    }
+
+    fn session_id(&self) -> Option<&str> {
+        match self.credential {
+            Credential::Session(ref sid) => Some(sid),
+            Credential::ApiKey(_) => None,
+        }
+    }
+
+    /// Only the owner of a resource or an admin may modify it
+    pub fn authorize(&self, owner_id: i32) -> Result<(), ApiError> {
+        if self.is_admin() || self.player_id == owner_id {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden)
+        }
+    }
+}
+
+///
+/// Accepts either an access token or an API key, sent as
+/// `Authorization: Bearer <credential>` or in the older `x-api-key` header.
+///
 impl<'a, 'r> FromRequest<'a, 'r> for ApiToken {
     type Error = ();
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<ApiToken, ()> {
-        let _conn = DbConn::from_request(request);
+        let conn = request.guard::<DbConn>()?;
+
+        let result = credential(request).and_then(|secret| {
+            if secret.starts_with(API_KEY_PREFIX) {
+                api_key_token(&secret[API_KEY_PREFIX.len()..], &conn)
+            } else {
+                session_token(secret, &conn)
+            }
+        });
 
-        let keys: Vec<_> = request.headers().get("x-api-key").collect();
-        if keys.len() != 1 {
-            return Outcome::Failure((Status::BadRequest, ()));
+        match result {
+            Ok(api_token) => Outcome::Success(api_token),
+            Err(status) => Outcome::Failure((status, ())),
         }
+    }
+}
 
-        let key = keys[0];
-        let validation = Validation::default();
-        match decode::<Claims>(&key, AUTH_SECRET.as_ref(), &validation) {
-            Ok(data) => {
-                let claims = data.claims;
-                let api_token = ApiToken {
-                    player_id: claims.id,
-                    _exp: claims.exp,
-                    _raw: key.to_string(),
-                };
-                Outcome::Success(api_token)
-            }
-            Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+impl<'a, 'r> FromRequest<'a, 'r> for MemberToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<MemberToken, ()> {
+        let token = request.guard::<ApiToken>()?;
+        if token.is_member() && token.session_id().is_some() {
+            Outcome::Success(MemberToken(token))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AdminToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<AdminToken, ()> {
+        let token = request.guard::<ApiToken>()?;
+        if token.is_admin() && token.session_id().is_some() {
+            Outcome::Success(AdminToken(token))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for GameSubmitterToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<GameSubmitterToken, ()> {
+        let token = request.guard::<ApiToken>()?;
+        if token.is_member() && token.has_scope(Scope::SubmitGames) {
+            Outcome::Success(GameSubmitterToken(token))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
         }
     }
 }
 
 #[post("/login", format = "application/json", data = "<req>")]
-pub fn login(req: Json<LoginRequest>, conn: DbConn) -> Result<Json<LoginResponse>, ApiError> {
+pub fn login(
+    req: Json<LoginRequest>,
+    conn: DbConn,
+    throttle: State<LoginThrottle>,
+    ip: ClientIp,
+) -> Result<Json<LoginResponse>, ApiError> {
     let login_req = req.into_inner();
-    let player = Player::find_by_email(&login_req.email, &conn)?;
 
-    if verify(&login_req.password, &player.password)? {
-        let token = create_token(player.id)?;
-        Ok(Json(LoginResponse { token }))
-    } else {
-        Err(ApiError::Unauthorized)
-    }
+    throttle.attempt(&ip, &login_req.email, || {
+        let player = Player::find_by_email(&login_req.email, &conn)?;
+
+        if PASSWORD_POLICY.verify(&login_req.password, &player.password)? {
+            match player.status() {
+                AccountStatus::Active => {}
+                AccountStatus::Pending => return Err(ApiError::PendingApproval),
+                AccountStatus::Rejected => return Err(ApiError::Forbidden),
+            }
+            if *REQUIRE_EMAIL_VERIFICATION && !player.verified {
+                return Err(ApiError::EmailNotVerified);
+            }
+
+            let response = conn.transaction::<_, ApiError, _>(|| {
+                let response = start_session(&player, &conn)?;
+                // The password is only ever known here, so this is when old hashes get upgraded
+                if PASSWORD_POLICY.needs_rehash(&player.password) {
+                    let new_hash = PASSWORD_POLICY.hash(&login_req.password)?;
+                    player.update_password(new_hash, &conn)?;
+                }
+                Ok(response)
+            })?;
+            Ok(Json(response))
+        } else {
+            Err(ApiError::Unauthorized)
+        }
+    })
+}
+
+///
+/// Exchanges a refresh token for a new access token and refresh token. Each
+/// refresh token can only be used once, if a used one comes back it has been
+/// copied and the whole session is revoked.
+///
+#[post("/refresh", format = "application/json", data = "<req>")]
+pub fn refresh_token(
+    req: Json<RefreshRequest>,
+    conn: DbConn,
+) -> Result<Json<LoginResponse>, ApiError> {
+    let refresh_req = req.into_inner();
+    let mut parts = refresh_req.refresh_token.splitn(2, '.');
+    let (selector, verifier) = match (parts.next(), parts.next()) {
+        (Some(selector), Some(verifier)) => (selector, verifier),
+        _ => return Err(ApiError::Unauthorized),
+    };
+
+    // Revoking a session has to be committed, so it is reported as `None` rather than an error
+    let response = conn.transaction::<_, ApiError, _>(|| {
+        let refresh = match RefreshToken::find_by_selector(selector, &conn).optional()? {
+            Some(refresh) => refresh,
+            None => return Err(ApiError::Unauthorized),
+        };
+        // Refresh tokens issued before verifiers were stored as digests still have a bcrypt hash
+        if refresh.verifier.starts_with("$2") {
+            if !verify(verifier, &refresh.verifier)? {
+                return Err(ApiError::Unauthorized);
+            }
+            RefreshToken::update_verifier(refresh.id, &digest(verifier), &conn)?;
+        } else if !digest_matches(verifier, &refresh.verifier) {
+            return Err(ApiError::Unauthorized);
+        }
+
+        if refresh.used {
+            warn!(
+                "Refresh token reused, revoking session {}",
+                refresh.token_id
+            );
+            Token::delete(refresh.token_id, &conn)?;
+            RefreshToken::delete_by_session(refresh.token_id, &conn)?;
+            return Ok(None);
+        }
+
+        let now = current_time();
+        let session = match Token::find_by_id(refresh.token_id, &conn).optional()? {
+            Some(ref session) if refresh.expires_at > now && session.expires_at > now => {
+                Token::extend(session.id, now + *REFRESH_TOKEN_VALIDITY, &conn)?
+            }
+            _ => return Err(ApiError::Unauthorized),
+        };
+
+        RefreshToken::mark_used(refresh.id, &conn)?;
+        // The role and status are read again so changes take effect on the next refresh
+        let player = Player::find_by_id(session.player_id, &conn)?;
+        if !may_sign_in(&player) {
+            return Err(ApiError::Unauthorized);
+        }
+        issue_tokens(&player, &session, &conn).map(Some)
+    })?;
+
+    response.map(Json).ok_or(ApiError::Unauthorized)
 }
 
-#[post("/refresh", format = "application/json")]
-pub fn refresh_token(_conn: DbConn, token: ApiToken) -> Result<Json<LoginResponse>, ApiError> {
-    let new_token = create_token(token.player_id)?;
-    Ok(Json(LoginResponse { token: new_token }))
+#[post("/logout")]
+pub fn logout(conn: DbConn, token: ApiToken) -> Result<(), ApiError> {
+    // API keys are revoked by deleting them instead
+    let sid = token.session_id().ok_or(ApiError::BadRequest)?;
+    Token::delete_by_content(sid, &conn)?;
+    Ok({})
+}
+
+#[post("/logout/all")]
+pub fn logout_all(conn: DbConn, token: MemberToken) -> Result<(), ApiError> {
+    // Requires a session so a leaked API key can't sign the player out everywhere
+    Token::delete_by_player(token.0.player_id, &conn)?;
+    Ok({})
 }
 
 #[put("/password", format = "application/json", data = "<req>")]
-pub fn change_password(req: Json<ChangePasswordRequest>, conn: DbConn) -> Result<(), ApiError> {
+pub fn change_password(
+    req: Json<ChangePasswordRequest>,
+    conn: DbConn,
+    throttle: State<LoginThrottle>,
+    ip: ClientIp,
+) -> Result<(), ApiError> {
     let change_password_req = req.into_inner();
 
-    let player = Player::find_by_email(&change_password_req.email, &conn)?;
-    let old_pass_is_valid = verify(&change_password_req.old_password, &player.password)?;
+    throttle.attempt(&ip, &change_password_req.email, || {
+        let player = Player::find_by_email(&change_password_req.email, &conn)?;
+        let old_pass_is_valid =
+            PASSWORD_POLICY.verify(&change_password_req.old_password, &player.password)?;
+
+        if old_pass_is_valid {
+            let mut validator = Validator::new();
+            PASSWORD_POLICY.check(
+                &mut validator,
+                "new_password",
+                &change_password_req.new_password,
+                &[player.alias.as_str(), player.email.as_str()],
+            );
+            validator.finish()?;
+
+            let new_hash = PASSWORD_POLICY.hash(&change_password_req.new_password)?;
+            conn.transaction::<_, ApiError, _>(|| {
+                // Anyone holding a token from before the change is logged out
+                Token::delete_by_player(player.id, &conn)?;
+                player.update_password(new_hash, &conn)?;
+                Ok({}) // just respond with 200 OK
+            })
+        } else {
+            Err(ApiError::Unauthorized)
+        }
+    })
+}
+
+///
+/// Emails a password reset token to the player. The response is the same
+/// whether or not the email belongs to anyone so it can't be used to find
+/// out who has an account, which is also why a failure to send is only
+/// logged. Requests are throttled like logins since each one sends mail.
+///
+#[post("/password/forgot", format = "application/json", data = "<req>")]
+pub fn forgot_password(
+    req: Json<ForgotPasswordRequest>,
+    conn: DbConn,
+    mailer: State<Box<dyn Mailer>>,
+    throttle: State<LoginThrottle>,
+    ip: ClientIp,
+) -> Result<(), ApiError> {
+    let forgot_password_req = req.into_inner();
+    throttle.limit("password_reset", &ip, &forgot_password_req.email, || {
+        send_password_reset(&forgot_password_req.email, &**mailer, &conn)
+    })
+}
+
+fn send_password_reset(
+    email: &str,
+    mailer: &dyn Mailer,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    let player = match Player::find_by_email(&email.to_string(), conn).optional()? {
+        Some(player) => player,
+        None => return Ok({}),
+    };
 
-    if old_pass_is_valid {
-        let new_hash = hash(&change_password_req.new_password, /*cost*/ 8)?;
-        let _ = player.update_password(new_hash, &conn);
-        Ok({}) // just respond with 200 OK
-    } else {
-        Err(ApiError::Unauthorized)
+    let token = one_time_token::issue(
+        player.id,
+        Purpose::PasswordReset,
+        *PASSWORD_RESET_VALIDITY,
+        conn,
+    )?;
+    let email = Email {
+        to: player.email,
+        subject: "Reset your Hawthorn password".to_string(),
+        body: format!(
+            "Someone asked to reset the password for {}. If it wasn't you, you can ignore this \
+             email.\n\nUse this within {} minutes to choose a new password:\n{}{}",
+            player.alias,
+            *PASSWORD_RESET_VALIDITY / 60,
+            *PASSWORD_RESET_URL,
+            token
+        ),
+    };
+
+    if let Err(message) = mailer.send(&email) {
+        error!("{}", message);
     }
+    Ok({})
 }
 
-fn create_token(id: i32) -> Result<String, JwtError> {
-    let claims = Claims {
-        id: id,
-        exp: current_time() + DEFAULT_TOKEN_VALIDITY,
+#[post("/password/reset", format = "application/json", data = "<req>")]
+pub fn reset_password(req: Json<ResetPasswordRequest>, conn: DbConn) -> Result<(), ApiError> {
+    let reset_password_req = req.into_inner();
+
+    conn.transaction::<_, ApiError, _>(|| {
+        let player_id =
+            one_time_token::redeem(&reset_password_req.token, Purpose::PasswordReset, &conn)?;
+        let player = Player::find_by_id(player_id, &conn)?;
+
+        let mut validator = Validator::new();
+        PASSWORD_POLICY.check(
+            &mut validator,
+            "new_password",
+            &reset_password_req.new_password,
+            &[player.alias.as_str(), player.email.as_str()],
+        );
+        validator.finish()?;
+
+        let new_hash = PASSWORD_POLICY.hash(&reset_password_req.new_password)?;
+        Token::delete_by_player(player.id, &conn)?;
+        player.update_password(new_hash, &conn)?;
+        Ok({})
+    })
+}
+
+///
+/// Finds the one credential sent with the request. Sending none is
+/// unauthorized, sending more than one is ambiguous.
+///
+fn credential<'a>(request: &'a Request) -> Result<&'a str, Status> {
+    let headers = request.headers();
+    let credentials = headers
+        .get("Authorization")
+        .filter_map(|value| match value.get(..BEARER.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(BEARER) => {
+                Some(value[BEARER.len()..].trim())
+            }
+            _ => None,
+        })
+        .chain(headers.get("x-api-key"))
+        .collect::<Vec<_>>();
+
+    match credentials.len() {
+        0 => Err(Status::Unauthorized),
+        1 => Ok(credentials[0]),
+        _ => Err(Status::BadRequest),
+    }
+}
+
+fn session_token(access_token: &str, conn: &SqliteConnection) -> Result<ApiToken, Status> {
+    let claims = KEYSET
+        .decode::<Claims>(access_token)
+        .ok_or(Status::Unauthorized)?;
+
+    // The session is gone once the player logs out
+    match Token::find_by_content(&claims.sid, conn).optional() {
+        Ok(Some(_)) => Ok(ApiToken {
+            player_id: claims.id,
+            role: claims.role,
+            credential: Credential::Session(claims.sid),
+        }),
+        Ok(None) => Err(Status::Unauthorized),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+///
+/// API keys look like `hk_<selector>.<verifier>`, see `api::api_key`. They
+/// act with the owner's current role. Keys created before verifiers were
+/// stored as SHA-256 digests still have a bcrypt hash, which is swapped for
+/// a digest the first time the key is used.
+///
+fn api_key_token(secret: &str, conn: &SqliteConnection) -> Result<ApiToken, Status> {
+    let mut parts = secret.splitn(2, '.');
+    let (selector, verifier) = match (parts.next(), parts.next()) {
+        (Some(selector), Some(verifier)) => (selector, verifier),
+        _ => return Err(Status::Unauthorized),
+    };
+
+    let key = match ApiKey::find_by_selector(selector, conn).optional() {
+        Ok(Some(key)) => key,
+        Ok(None) => return Err(Status::Unauthorized),
+        Err(_) => return Err(Status::InternalServerError),
     };
-    encode(&Header::default(), &claims, AUTH_SECRET.as_ref())
+    if key.verifier.starts_with("$2") {
+        match verify(verifier, &key.verifier) {
+            Ok(true) => ApiKey::update_verifier(key.id, &digest(verifier), conn)
+                .map_err(|_| Status::InternalServerError)?,
+            Ok(false) => return Err(Status::Unauthorized),
+            Err(_) => return Err(Status::InternalServerError),
+        };
+    } else if !digest_matches(verifier, &key.verifier) {
+        return Err(Status::Unauthorized);
+    }
+
+    let player = Player::find_by_id(key.player_id, conn).map_err(|_| Status::Unauthorized)?;
+    if !may_sign_in(&player) {
+        return Err(Status::Unauthorized);
+    }
+    // Keys can be used for every request, so don't write on every one of them
+    let now = current_time();
+    if key.last_used_at.map_or(true, |last_used_at| {
+        now - last_used_at >= API_KEY_TOUCH_INTERVAL
+    }) {
+        ApiKey::touch(key.id, now, conn).map_err(|_| Status::InternalServerError)?;
+    }
+
+    Ok(ApiToken {
+        player_id: player.id,
+        role: player.role(),
+        credential: Credential::ApiKey(key.scopes()),
+    })
+}
+
+///
+/// Whether the player can still use their sessions and API keys, which is
+/// everyone `login` would let in.
+///
+fn may_sign_in(player: &Player) -> bool {
+    player.status() == AccountStatus::Active && (player.verified || !*REQUIRE_EMAIL_VERIFICATION)
+}
+
+fn start_session(player: &Player, conn: &SqliteConnection) -> Result<LoginResponse, ApiError> {
+    let now = current_time();
+    Token::delete_expired(now, conn)?;
+    RefreshToken::delete_expired(now, conn)?;
+
+    let session = Token::insert(
+        NewToken {
+            player_id: player.id,
+            content: random_string(SESSION_ID_LENGTH),
+            expires_at: now + *REFRESH_TOKEN_VALIDITY,
+        },
+        conn,
+    )?;
+    issue_tokens(player, &session, conn)
 }
 
-fn current_time() -> i64 {
-    time::get_time().sec
+///
+/// Issues an access token for the session along with the refresh token that
+/// replaces it. Only a SHA-256 digest of the refresh token's verifier is
+/// stored.
+///
+fn issue_tokens(
+    player: &Player,
+    session: &Token,
+    conn: &SqliteConnection,
+) -> Result<LoginResponse, ApiError> {
+    let selector = random_string(SELECTOR_LENGTH);
+    let verifier = random_string(VERIFIER_LENGTH);
+    RefreshToken::insert(
+        NewRefreshToken {
+            token_id: session.id,
+            selector: selector.clone(),
+            verifier: digest(&verifier),
+            used: false,
+            expires_at: session.expires_at,
+        },
+        conn,
+    )?;
+
+    let claims = Claims {
+        id: player.id,
+        exp: current_time() + *ACCESS_TOKEN_VALIDITY,
+        role: player.role(),
+        sid: session.content.clone(),
+    };
+    Ok(LoginResponse {
+        token: KEYSET.encode(&claims)?,
+        refresh_token: format!("{}.{}", selector, verifier),
+        expires_in: *ACCESS_TOKEN_VALIDITY,
+    })
 }
@@ -0,0 +1,362 @@
+use api::auth::ApiToken;
+use api::common::{current_time, ListParams, PaginatedResponse, Sortable};
+use api::deck::DeckResponse;
+use api::error::ApiError;
+use api::game::DEFAULT_ELO;
+use config;
+use db::DbConn;
+use models::deck::Deck;
+use models::game::Game;
+use models::participant::Participant;
+use rocket::request::Form;
+use rocket_contrib::json::Json;
+use std::collections::HashMap;
+
+lazy_static! {
+    /// How many weeks of history the popularity trend covers
+    pub static ref COMMANDER_TREND_WEEKS: i32 = trend_weeks_from_env();
+}
+
+const SECONDS_PER_WEEK: f64 = 604800.0;
+
+#[derive(Serialize)]
+pub struct CommanderResponse {
+    /// The normalized name, used to look the commander up
+    key: String,
+    /// The most common spelling among the decks
+    name: String,
+    deck_count: i32,
+    games: i32,
+    wins: i32,
+    win_rate: f64,
+    /// The average current rating of the decks
+    average_elo: f64,
+    active: bool,
+    trend: Vec<TrendPoint>,
+}
+
+#[derive(Serialize)]
+pub struct CommanderDetailResponse {
+    #[serde(flatten)]
+    commander: CommanderResponse,
+    decks: Vec<DeckResponse>,
+}
+
+///
+/// One week of the popularity trend. `share` is the part of every deck that
+/// sat down at a table that week which was led by this commander.
+///
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TrendPoint {
+    from: i64,
+    games: i32,
+    share: f64,
+}
+
+impl Sortable for CommanderResponse {
+    fn elo(&self) -> f64 {
+        self.average_elo
+    }
+
+    fn games(&self) -> i32 {
+        self.games
+    }
+
+    fn win_rate(&self) -> f64 {
+        self.win_rate
+    }
+
+    fn alias(&self) -> &str {
+        &self.name
+    }
+}
+
+#[get("/?<params..>")]
+pub fn get_commanders(
+    params: Form<ListParams>,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<PaginatedResponse<CommanderResponse>>, ApiError> {
+    let options = params.validate()?;
+    let search = options.search.as_ref().map(|search| normalize(search));
+
+    let commanders = load_commanders(&conn)?
+        .into_iter()
+        .map(|detail| detail.commander)
+        .filter(|commander| {
+            search
+                .as_ref()
+                .map_or(true, |search| commander.key.contains(search.as_str()))
+        })
+        .filter(|commander| {
+            options
+                .active
+                .map_or(true, |active| commander.active == active)
+        })
+        .collect();
+
+    Ok(Json(options.paginate(commanders, "/commanders")))
+}
+
+#[get("/<key>")]
+pub fn get_commander(
+    key: String,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<CommanderDetailResponse>, ApiError> {
+    let key = normalize(&key);
+    load_commanders(&conn)?
+        .into_iter()
+        .find(|detail| detail.commander.key == key)
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+///
+/// Commander names are typed in by hand, so they are compared ignoring case,
+/// punctuation and spacing. "Atraxa, Praetors' Voice" becomes
+/// `atraxa-praetors-voice`.
+///
+pub fn normalize(commander: &str) -> String {
+    commander
+        .to_lowercase()
+        .chars()
+        .filter(|&c| c != '\'' && c != '’')
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn load_commanders(conn: &DbConn) -> Result<Vec<CommanderDetailResponse>, ApiError> {
+    let decks = Deck::all(&conn)?;
+    let history = Participant::all_by_deck_join_game(decks, None, &conn)?;
+    Ok(summarize(
+        history,
+        *COMMANDER_TREND_WEEKS,
+        current_time() as f64,
+    ))
+}
+
+fn trend_weeks_from_env() -> i32 {
+    let weeks = config::env_or("COMMANDER_TREND_WEEKS", 8);
+    if weeks < 0 {
+        panic!("COMMANDER_TREND_WEEKS ({}) must not be negative", weeks);
+    }
+    weeks
+}
+
+///
+/// Groups decks by commander and works out their stats, ordered by key.
+/// The trend covers the `weeks` weeks leading up to `now`, oldest first.
+///
+fn summarize(
+    history: Vec<(Deck, Vec<(Participant, Game)>)>,
+    weeks: i32,
+    now: f64,
+) -> Vec<CommanderDetailResponse> {
+    let start = now - weeks as f64 * SECONDS_PER_WEEK;
+    let week_of = |time_stamp: f64| {
+        if time_stamp < start || time_stamp >= now {
+            None
+        } else {
+            Some(((time_stamp - start) / SECONDS_PER_WEEK) as usize)
+        }
+    };
+
+    let mut total_by_week = vec![0; weeks as usize];
+    let mut groups: HashMap<String, Vec<(Deck, Vec<(Participant, Game)>)>> = HashMap::new();
+    for (deck, participations) in history {
+        for &(_, ref game) in participations.iter() {
+            if let Some(week) = week_of(game.time_stamp) {
+                total_by_week[week] += 1;
+            }
+        }
+        groups
+            .entry(normalize(&deck.commander))
+            .or_insert_with(Vec::new)
+            .push((deck, participations));
+    }
+
+    let mut commanders = groups
+        .into_iter()
+        .filter(|&(ref key, _)| !key.is_empty())
+        .map(|(key, group)| {
+            let mut games_by_week = vec![0; weeks as usize];
+            let mut spellings: Vec<(String, usize)> = vec![];
+            let mut decks = vec![];
+            for (deck, participations) in group {
+                for &(_, ref game) in participations.iter() {
+                    if let Some(week) = week_of(game.time_stamp) {
+                        games_by_week[week] += 1;
+                    }
+                }
+                match spellings.iter().position(|s| s.0 == deck.commander) {
+                    Some(index) => spellings[index].1 += 1,
+                    None => spellings.push((deck.commander.clone(), 1)),
+                }
+
+                let (participations, _): (Vec<_>, Vec<_>) = participations.into_iter().unzip();
+                decks.push(DeckResponse::new(deck, participations));
+            }
+
+            // The first spelling wins a tie, which is the oldest deck's
+            let name = spellings
+                .iter()
+                .fold(
+                    None,
+                    |best: Option<&(String, usize)>, spelling| match best {
+                        Some(best) if best.1 >= spelling.1 => Some(best),
+                        _ => Some(spelling),
+                    },
+                )
+                .map_or_else(String::new, |spelling| spelling.0.clone());
+
+            let games = decks.iter().map(|deck| deck.games).sum::<i32>();
+            let wins = decks.iter().map(|deck| deck.wins).sum::<i32>();
+            let trend = games_by_week
+                .iter()
+                .zip(total_by_week.iter())
+                .enumerate()
+                .map(|(week, (&games, &total))| TrendPoint {
+                    from: (start + week as f64 * SECONDS_PER_WEEK) as i64,
+                    games,
+                    share: if total > 0 {
+                        games as f64 / total as f64
+                    } else {
+                        0.0
+                    },
+                })
+                .collect();
+
+            let commander = CommanderResponse {
+                key,
+                name,
+                deck_count: decks.len() as i32,
+                games,
+                wins,
+                win_rate: if games > 0 {
+                    wins as f64 / games as f64
+                } else {
+                    0.0
+                },
+                average_elo: if decks.is_empty() {
+                    DEFAULT_ELO
+                } else {
+                    decks.iter().map(|deck| deck.elo).sum::<f64>() / decks.len() as f64
+                },
+                active: decks.iter().any(|deck| deck.active),
+                trend,
+            };
+            CommanderDetailResponse { commander, decks }
+        })
+        .collect::<Vec<_>>();
+
+    commanders.sort_by(|a, b| a.commander.key.cmp(&b.commander.key));
+    commanders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1546300800.0;
+
+    fn deck(id: i32, commander: &str, active: bool) -> Deck {
+        Deck {
+            id,
+            alias: format!("deck {}", id),
+            commander: commander.to_string(),
+            player_id: 1,
+            active,
+        }
+    }
+
+    /// A participation `weeks_ago` weeks before `NOW`, newest first like the database returns them
+    fn played(deck_id: i32, win: bool, elo: f64, weeks_ago: f64) -> (Participant, Game) {
+        let game = Game {
+            id: 0,
+            time_stamp: NOW - weeks_ago * SECONDS_PER_WEEK,
+            league_id: None,
+        };
+        let participant = Participant {
+            id: 0,
+            game_id: 0,
+            deck_id,
+            win,
+            elo,
+            deviation: 350.0,
+            volatility: 0.06,
+            games_played: 0,
+            previous_elo: 1000.0,
+        };
+        (participant, game)
+    }
+
+    #[test]
+    fn test_normalizes_spelling() {
+        assert_eq!(
+            normalize("Atraxa, Praetors' Voice"),
+            "atraxa-praetors-voice"
+        );
+        assert_eq!(
+            normalize("  atraxa praetors   VOICE "),
+            "atraxa-praetors-voice"
+        );
+        assert_eq!(
+            normalize("Yuriko, the Tiger’s Shadow"),
+            "yuriko-the-tigers-shadow"
+        );
+    }
+
+    #[test]
+    fn test_groups_decks_by_commander() {
+        let history = vec![
+            (
+                deck(1, "Atraxa, Praetors' Voice", true),
+                vec![played(1, true, 1030.0, 0.5), played(1, false, 990.0, 1.5)],
+            ),
+            (
+                deck(2, "atraxa praetors voice", false),
+                vec![played(2, true, 1010.0, 0.5)],
+            ),
+            (deck(3, "Atraxa, Praetors' Voice", false), vec![]),
+            (
+                deck(4, "Edgar Markov", true),
+                vec![played(4, false, 970.0, 0.5)],
+            ),
+        ];
+
+        let commanders = summarize(history, 2, NOW);
+        let keys = commanders
+            .iter()
+            .map(|c| c.commander.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["atraxa-praetors-voice", "edgar-markov"]);
+
+        let atraxa = &commanders[0].commander;
+        assert_eq!(atraxa.name, "Atraxa, Praetors' Voice");
+        assert_eq!(atraxa.deck_count, 3);
+        assert_eq!(atraxa.games, 3);
+        assert_eq!(atraxa.wins, 2);
+        assert_eq!(atraxa.average_elo, (1030.0 + 1010.0 + DEFAULT_ELO) / 3.0);
+        assert!(atraxa.active);
+        assert_eq!(
+            atraxa.trend,
+            vec![
+                TrendPoint {
+                    from: (NOW - 2.0 * SECONDS_PER_WEEK) as i64,
+                    games: 1,
+                    share: 1.0,
+                },
+                TrendPoint {
+                    from: (NOW - SECONDS_PER_WEEK) as i64,
+                    games: 2,
+                    share: 2.0 / 3.0,
+                },
+            ]
+        );
+        assert_eq!(commanders[1].decks.len(), 1);
+    }
+}
@@ -1,6 +1,348 @@
+use api::error::ApiError;
+use api::validation::Validator;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::http::uri::Uri;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::str::FromStr;
+use time;
+
+pub const DEFAULT_LIMIT: i32 = 25;
+pub const DEFAULT_OFFSET: i32 = 0;
+pub const MAX_LIMIT: i32 = 100;
+
 #[derive(Serialize)]
 pub struct PaginatedResponse<T> {
     pub limit: i32,
     pub offset: i32,
+    /// How many items match the request across every page
+    pub total: i64,
+    /// Link to the following page, if there is one
+    pub next: Option<String>,
     pub data: Vec<T>,
 }
+
+///
+/// Query parameters shared by the player and deck lists. `search` matches
+/// part of an alias or commander and `active` only keeps active decks, or
+/// players with at least one.
+///
+#[derive(FromForm, Default, Debug)]
+pub struct ListParams {
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub search: Option<String>,
+    pub active: Option<bool>,
+}
+
+/// `ListParams` after validation, with the defaults filled in
+#[derive(Debug, PartialEq)]
+pub struct ListOptions {
+    pub limit: i32,
+    pub offset: i32,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub search: Option<String>,
+    pub active: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Elo,
+    Games,
+    WinRate,
+    Alias,
+}
+
+///
+/// Anything a list can be sorted by. Ties keep the order the items were
+/// loaded in, which is by id.
+///
+pub trait Sortable {
+    fn elo(&self) -> f64;
+    fn games(&self) -> i32;
+    fn win_rate(&self) -> f64;
+    fn alias(&self) -> &str;
+}
+
+impl ListParams {
+    pub fn validate(&self) -> Result<ListOptions, ApiError> {
+        let mut validator = Validator::new();
+        check_page(&mut validator, self.limit, self.offset);
+
+        let sort = match self.sort {
+            Some(ref sort) => sort.parse::<SortKey>().ok(),
+            None => Some(SortKey::Alias),
+        };
+        validator.check(
+            sort.is_some(),
+            "sort",
+            "must be one of elo, games, win_rate or alias",
+        );
+
+        let descending = match self.order.as_ref().map(String::as_str) {
+            Some("asc") => Some(false),
+            Some("desc") => Some(true),
+            Some(_) => None,
+            // Highest ratings first, but aliases from A to Z
+            None => Some(sort != Some(SortKey::Alias)),
+        };
+        validator.check(descending.is_some(), "order", "must be asc or desc");
+        validator.finish()?;
+
+        Ok(ListOptions {
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT),
+            offset: self.offset.unwrap_or(DEFAULT_OFFSET),
+            sort: sort.unwrap_or(SortKey::Alias),
+            descending: descending.unwrap_or(false),
+            search: self.search.clone().filter(|search| !search.is_empty()),
+            active: self.active,
+        })
+    }
+}
+
+impl ListOptions {
+    ///
+    /// Sorts every matching item and cuts out the requested page. `path` is
+    /// where the list is mounted, used for the `next` link.
+    ///
+    pub fn paginate<T: Sortable>(&self, mut items: Vec<T>, path: &str) -> PaginatedResponse<T> {
+        items.sort_by(|a, b| {
+            let ordering = self.sort.compare(a, b);
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        let total = items.len() as i64;
+        let data = items
+            .into_iter()
+            .skip(self.offset as usize)
+            .take(self.limit as usize)
+            .collect();
+        self.page(data, total, path)
+    }
+
+    fn page<T>(&self, data: Vec<T>, total: i64, path: &str) -> PaginatedResponse<T> {
+        let next = if ((self.offset + self.limit) as i64) < total {
+            Some(self.next_link(path))
+        } else {
+            None
+        };
+
+        PaginatedResponse {
+            limit: self.limit,
+            offset: self.offset,
+            total,
+            next,
+            data,
+        }
+    }
+
+    fn next_link(&self, path: &str) -> String {
+        let mut query = vec![
+            format!("limit={}", self.limit),
+            format!("offset={}", self.offset + self.limit),
+            format!("sort={}", self.sort.as_str()),
+            format!("order={}", if self.descending { "desc" } else { "asc" }),
+        ];
+        if let Some(ref search) = self.search {
+            query.push(format!("search={}", Uri::percent_encode(search)));
+        }
+        if let Some(active) = self.active {
+            query.push(format!("active={}", active));
+        }
+        format!("{}?{}", path, query.join("&"))
+    }
+}
+
+impl SortKey {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SortKey::Elo => "elo",
+            SortKey::Games => "games",
+            SortKey::WinRate => "win_rate",
+            SortKey::Alias => "alias",
+        }
+    }
+
+    fn compare<T: Sortable>(&self, a: &T, b: &T) -> Ordering {
+        match *self {
+            SortKey::Elo => a.elo().partial_cmp(&b.elo()).unwrap_or(Ordering::Equal),
+            SortKey::Games => a.games().cmp(&b.games()),
+            SortKey::WinRate => a
+                .win_rate()
+                .partial_cmp(&b.win_rate())
+                .unwrap_or(Ordering::Equal),
+            SortKey::Alias => a.alias().to_lowercase().cmp(&b.alias().to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "elo" => Ok(SortKey::Elo),
+            "games" => Ok(SortKey::Games),
+            "win_rate" => Ok(SortKey::WinRate),
+            "alias" => Ok(SortKey::Alias),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Checks `limit` and `offset` query parameters
+pub fn check_page(validator: &mut Validator, limit: Option<i32>, offset: Option<i32>) {
+    if let Some(limit) = limit {
+        validator.check(
+            limit > 0 && limit <= MAX_LIMIT,
+            "limit",
+            format!("must be between 1 and {}", MAX_LIMIT),
+        );
+    }
+    if let Some(offset) = offset {
+        validator.check(offset >= 0, "offset", "must not be negative");
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn current_time() -> i64 {
+    time::get_time().sec
+}
+
+/// A random alphanumeric string suitable for use as a secret
+pub fn random_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .collect()
+}
+
+///
+/// Hex encoded SHA-256 of `secret`. Only for secrets we generate ourselves,
+/// which are long and random enough not to need a slow password hash.
+///
+pub fn digest(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Compares `secret` with a `digest` of it in constant time
+pub fn digest_matches(secret: &str, expected: &str) -> bool {
+    let actual = digest(secret);
+    actual.len() == expected.len()
+        && actual
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item(&'static str, f64, i32);
+
+    impl Sortable for Item {
+        fn elo(&self) -> f64 {
+            self.1
+        }
+
+        fn games(&self) -> i32 {
+            self.2
+        }
+
+        fn win_rate(&self) -> f64 {
+            0.0
+        }
+
+        fn alias(&self) -> &str {
+            self.0
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item("carol", 1010.0, 3),
+            Item("Alice", 990.0, 9),
+            Item("bob", 1050.0, 1),
+        ]
+    }
+
+    fn aliases<T: Sortable>(page: &PaginatedResponse<T>) -> Vec<&str> {
+        page.data.iter().map(|item| item.alias()).collect()
+    }
+
+    #[test]
+    fn test_defaults_to_alias_ascending() {
+        let options = ListParams::default().validate().unwrap();
+        assert_eq!(options.sort, SortKey::Alias);
+        assert!(!options.descending);
+
+        let page = options.paginate(items(), "/decks");
+        assert_eq!(aliases(&page), vec!["Alice", "bob", "carol"]);
+        assert_eq!(page.total, 3);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn test_stats_sort_highest_first() {
+        let params = ListParams {
+            sort: Some("elo".to_string()),
+            ..Default::default()
+        };
+        let page = params.validate().unwrap().paginate(items(), "/decks");
+        assert_eq!(aliases(&page), vec!["bob", "carol", "Alice"]);
+    }
+
+    #[test]
+    fn test_pages_link_to_the_next_one() {
+        let params = ListParams {
+            limit: Some(2),
+            sort: Some("games".to_string()),
+            order: Some("asc".to_string()),
+            active: Some(true),
+            ..Default::default()
+        };
+        let page = params.validate().unwrap().paginate(items(), "/players");
+        assert_eq!(aliases(&page), vec!["bob", "carol"]);
+        assert_eq!(page.total, 3);
+        assert_eq!(
+            page.next,
+            Some("/players?limit=2&offset=2&sort=games&order=asc&active=true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_sort_and_order() {
+        let params = ListParams {
+            sort: Some("colour".to_string()),
+            order: Some("sideways".to_string()),
+            limit: Some(0),
+            ..Default::default()
+        };
+        match params.validate() {
+            Err(ApiError::Validation(errors)) => {
+                let fields = errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>();
+                assert_eq!(fields, vec!["limit", "sort", "order"]);
+            }
+            _ => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn test_digest_matches_only_its_secret() {
+        let expected = digest("secret");
+        assert_eq!(expected.len(), 64);
+        assert!(digest_matches("secret", &expected));
+        assert!(!digest_matches("Secret", &expected));
+        assert!(!digest_matches("secret", &expected[..63]));
+    }
+}
@@ -1,12 +1,15 @@
-use api::auth::ApiToken;
+use api::auth::{ApiToken, MemberToken};
+use api::common::{current_time, ListParams, PaginatedResponse, Sortable};
+use api::elo::Rankable;
 use api::error::ApiError;
 use api::game::DEFAULT_ELO;
+use api::leaderboard::{Criteria, LeaderboardParams, Standing};
 use db::DbConn;
 use models::deck::{Deck, NewDeck};
+use models::league::{League, LeagueMember};
 use models::participant::Participant;
+use rocket::request::Form;
 use rocket_contrib::json::Json;
-use std::cmp::Ordering;
-use time;
 
 #[derive(Deserialize)]
 pub struct DeckRequest {
@@ -25,16 +28,16 @@ pub struct UpdateDeckRequest {
 
 #[derive(Serialize)]
 pub struct DeckResponse {
-    id: i32,
-    alias: String,
-    commander: String,
-    player_id: i32,
-    active: bool,
-    games: i32,
-    wins: i32,
-    win_percentage: f64,
-    elo: f64,
-    elo_delta: f64,
+    pub id: i32,
+    pub alias: String,
+    pub commander: String,
+    pub player_id: i32,
+    pub active: bool,
+    pub games: i32,
+    pub wins: i32,
+    pub win_percentage: f64,
+    pub elo: f64,
+    pub elo_delta: f64,
 }
 
 impl Deck {
@@ -49,7 +52,51 @@ impl Deck {
     }
 }
 
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    rank: usize,
+    /// What the deck was ranked by, see `rank_by`
+    score: f64,
+    #[serde(flatten)]
+    deck: DeckResponse,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardStatus {
+    id: i32,
+    /// Whether the deck meets the criteria
+    eligible: bool,
+    /// Whether it made it into the returned leaderboard
+    listed: bool,
+    rank: Option<usize>,
+    score: f64,
+    reasons: Vec<String>,
+    criteria: Criteria,
+}
+
+impl Sortable for DeckResponse {
+    fn elo(&self) -> f64 {
+        self.elo
+    }
+
+    fn games(&self) -> i32 {
+        self.games
+    }
+
+    fn win_rate(&self) -> f64 {
+        self.win_percentage
+    }
+
+    fn alias(&self) -> &str {
+        &self.alias
+    }
+}
+
 impl DeckResponse {
+    ///
+    /// Stats from `participations`, newest first. Outside of a league's own
+    /// leaderboard these are the games played outside of any league.
+    ///
     pub fn new(deck: Deck, participations: Vec<Participant>) -> DeckResponse {
         let games = participations.len() as i32;
         let wins = participations.iter().filter(|&p| p.win == true).count() as i32;
@@ -88,16 +135,21 @@ impl DeckResponse {
     }
 }
 
-#[get("/")]
-pub fn get_decks(conn: DbConn, _token: ApiToken) -> Result<Json<Vec<DeckResponse>>, ApiError> {
-    let decks = Deck::all(&conn)?;
-    let participants = Participant::all_grouped_by_deck(decks, &conn)?;
+#[get("/?<params..>")]
+pub fn get_decks(
+    params: Form<ListParams>,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<PaginatedResponse<DeckResponse>>, ApiError> {
+    let options = params.validate()?;
+    let decks = Deck::search(
+        options.search.as_ref().map(String::as_str),
+        options.active,
+        &conn,
+    )?;
 
-    let response = participants
-        .into_iter()
-        .map(|(deck, participations)| DeckResponse::new(deck, participations))
-        .collect();
-    Ok(Json(response))
+    let response = DeckResponse::into_deck_response(decks, &conn)?;
+    Ok(Json(options.paginate(response, "/decks")))
 }
 
 #[get("/<id>")]
@@ -108,46 +160,81 @@ pub fn get_deck(id: i32, conn: DbConn, _token: ApiToken) -> Result<Json<DeckResp
     Ok(Json(response))
 }
 
-#[get("/leaderboard")]
+#[get("/leaderboard?<params..>")]
 pub fn get_leaderboard(
+    params: Form<LeaderboardParams>,
     conn: DbConn,
     _token: ApiToken,
-) -> Result<Json<Vec<DeckResponse>>, ApiError> {
-    let decks = Deck::all(&conn)?;
-    let query_result = Participant::all_by_deck_join_game(decks, &conn)?;
-    let time_four_weeks_ago = current_time() - 2419200.0; /*four weeks*/
-    let mut leaderboard = query_result
+) -> Result<Json<Vec<LeaderboardEntry>>, ApiError> {
+    let criteria = params.criteria()?;
+    let ranked = criteria.rank(deck_standings(&criteria, &conn)?, current_time() as f64);
+
+    let response = ranked
         .into_iter()
-        .filter(|(deck, participations)| {
-            let max_time = participations
-                .iter()
-                .map(|(_, g)| g.time_stamp)
-                .fold(0.0, f64::max);
-            deck.active && participations.len() >= 5 && max_time > time_four_weeks_ago
-        })
-        .map(|(deck, participations)| {
-            let (parts, _): (Vec<_>, Vec<_>) = participations.into_iter().unzip();
-            DeckResponse::new(deck, parts)
+        .take(criteria.size as usize)
+        .map(|ranked| LeaderboardEntry {
+            rank: ranked.rank,
+            score: ranked.standing.score,
+            deck: ranked.entry,
         })
-        .collect::<Vec<DeckResponse>>();
+        .collect();
+    Ok(Json(response))
+}
 
-    leaderboard.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap_or(Ordering::Less));
+///
+/// Where a single deck stands with the same parameters as the leaderboard,
+/// and every reason it isn't on it.
+///
+#[get("/leaderboard/<id>?<params..>")]
+pub fn explain_leaderboard(
+    id: i32,
+    params: Form<LeaderboardParams>,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<LeaderboardStatus>, ApiError> {
+    let criteria = params.criteria()?;
+    let now = current_time() as f64;
+    let standings = deck_standings(&criteria, &conn)?;
+    let standing = standings
+        .iter()
+        .find(|&&(ref deck, _)| deck.id == id)
+        .map(|&(_, standing)| standing)
+        .ok_or(ApiError::NotFound)?;
 
-    let response = leaderboard
+    let mut reasons = criteria.exclusions(&standing, now);
+    let rank = criteria
+        .rank(standings, now)
         .into_iter()
-        .take(20)
-        .collect::<Vec<DeckResponse>>();
+        .find(|ranked| ranked.entry.id == id)
+        .map(|ranked| ranked.rank);
+    if let Some(rank) = rank {
+        if rank > criteria.size as usize {
+            reasons.push(format!(
+                "is ranked {}, outside the top {}",
+                rank, criteria.size
+            ));
+        }
+    }
 
-    Ok(Json(response))
+    Ok(Json(LeaderboardStatus {
+        id,
+        eligible: rank.is_some(),
+        listed: reasons.is_empty(),
+        rank,
+        score: standing.score,
+        reasons,
+        criteria,
+    }))
 }
 
 #[post("/", format = "application/json", data = "<req>")]
 pub fn create_deck(
     req: Json<DeckRequest>,
     conn: DbConn,
-    _token: ApiToken,
+    token: MemberToken,
 ) -> Result<Json<DeckResponse>, ApiError> {
     let deck_request = req.into_inner();
+    token.0.authorize(deck_request.player_id)?;
 
     let new_deck = NewDeck {
         alias: deck_request.alias,
@@ -177,10 +264,11 @@ pub fn create_deck(
 pub fn update_deck(
     json: Json<UpdateDeckRequest>,
     conn: DbConn,
-    _token: ApiToken,
+    token: MemberToken,
 ) -> Result<Json<DeckResponse>, ApiError> {
     let req = json.into_inner();
     let current_deck = Deck::find_by_id(req.id, &conn)?;
+    token.0.authorize(current_deck.player_id)?;
     let updated_deck = Deck::update(current_deck.update_from(req), &conn)?;
 
     let participations = Participant::find_by_deck(&updated_deck, &conn)?;
@@ -189,7 +277,45 @@ pub fn update_deck(
     Ok(Json(response))
 }
 
-fn current_time() -> f64 {
-    let timespec = time::get_time();
-    timespec.sec as f64
+///
+/// Every deck with its stats and what the leaderboard needs to know about it.
+/// In a league that's the decks of its members and the games played there,
+/// otherwise every deck and the games played outside of any league.
+///
+pub fn deck_standings(
+    criteria: &Criteria,
+    conn: &DbConn,
+) -> Result<Vec<(DeckResponse, Standing)>, ApiError> {
+    let decks = match criteria.league_id {
+        Some(league_id) => {
+            let league = League::find_by_id(league_id, &conn)?;
+            let members = LeagueMember::player_ids(league.id, &conn)?;
+            Deck::all(&conn)?
+                .into_iter()
+                .filter(|deck| members.contains(&deck.player_id))
+                .collect()
+        }
+        None => Deck::all(&conn)?,
+    };
+    // Every league keeps its own ratings
+    let standings = Participant::all_by_deck_join_game(decks, criteria.league_id, &conn)?
+        .into_iter()
+        .map(|(deck, history)| {
+            let last_played = history.first().map(|&(_, ref game)| game.time_stamp);
+            let (participations, _): (Vec<_>, Vec<_>) = history.into_iter().unzip();
+            let ratings = participations
+                .iter()
+                .map(|p| p.get_rating())
+                .collect::<Vec<_>>();
+
+            let standing = Standing {
+                active: deck.active,
+                games: participations.len() as i32,
+                last_played,
+                score: criteria.rank_by.score(&ratings).unwrap_or(DEFAULT_ELO),
+            };
+            (DeckResponse::new(deck, participations), standing)
+        })
+        .collect();
+    Ok(standings)
 }
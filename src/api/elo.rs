@@ -1,8 +1,14 @@
+use api::rating::Rating;
+use config;
 use models::participant::{NewParticipant, Participant};
 use std::collections::HashMap;
 
 pub const DEFAULT_IMPACT: f64 = 40.0;
 
+lazy_static! {
+    pub static ref K_FACTOR_SCHEDULE: KFactorSchedule = KFactorSchedule::from_env();
+}
+
 #[derive(PartialEq, Clone, Copy, Serialize)]
 pub enum GameOutcome {
     WIN,
@@ -15,6 +21,31 @@ where
     Self: Sized,
 {
     fn compute_elo(entities: &Vec<Self>) -> Vec<Self>;
+    fn compute_elo_with(entities: &Vec<Self>, schedule: &KFactorSchedule) -> Vec<Self>;
+}
+
+///
+/// Decides how much a single game is allowed to move a rating. New decks
+/// get `provisional_k` for their first `provisional_games` games so they
+/// find their level quickly, after which K tapers linearly from `k` down to
+/// `minimum_k` over `taper_games` games. Established decks can additionally
+/// be capped by rating bands. The default is a flat `DEFAULT_IMPACT`.
+///
+#[derive(PartialEq, Clone, Debug)]
+pub struct KFactorSchedule {
+    pub provisional_games: i32,
+    pub provisional_k: f64,
+    pub k: f64,
+    pub minimum_k: f64,
+    pub taper_games: i32,
+    pub bands: Vec<RatingBand>,
+}
+
+/// Caps K at `k` for decks rated at or above `min_rating`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct RatingBand {
+    pub min_rating: f64,
+    pub k: f64,
 }
 
 pub trait Rankable
@@ -25,6 +56,18 @@ where
     fn get_unique_id(&self) -> i32;
     fn get_win(&self) -> bool;
     fn get_elo(&self) -> f64;
+    fn get_deviation(&self) -> f64;
+    fn get_volatility(&self) -> f64;
+    fn get_games_played(&self) -> i32;
+    fn clone_with_rating(&self, rating: Rating) -> Self;
+
+    fn get_rating(&self) -> Rating {
+        Rating {
+            elo: self.get_elo(),
+            deviation: self.get_deviation(),
+            volatility: self.get_volatility(),
+        }
+    }
 }
 
 impl Rankable for Participant {
@@ -35,6 +78,10 @@ impl Rankable for Participant {
             game_id: self.game_id,
             win: self.win,
             elo: new_elo,
+            deviation: self.deviation,
+            volatility: self.volatility,
+            games_played: self.games_played,
+            previous_elo: self.previous_elo,
         }
     }
 
@@ -49,6 +96,32 @@ impl Rankable for Participant {
     fn get_elo(&self) -> f64 {
         self.elo
     }
+
+    fn get_deviation(&self) -> f64 {
+        self.deviation
+    }
+
+    fn get_volatility(&self) -> f64 {
+        self.volatility
+    }
+
+    fn get_games_played(&self) -> i32 {
+        self.games_played
+    }
+
+    fn clone_with_rating(&self, rating: Rating) -> Self {
+        Participant {
+            id: self.id,
+            deck_id: self.deck_id,
+            game_id: self.game_id,
+            win: self.win,
+            elo: rating.elo,
+            deviation: rating.deviation,
+            volatility: rating.volatility,
+            games_played: self.games_played,
+            previous_elo: self.previous_elo,
+        }
+    }
 }
 
 impl Rankable for NewParticipant {
@@ -58,6 +131,10 @@ impl Rankable for NewParticipant {
             game_id: self.game_id,
             win: self.win,
             elo: new_elo,
+            deviation: self.deviation,
+            volatility: self.volatility,
+            games_played: self.games_played,
+            previous_elo: self.previous_elo,
         }
     }
     fn get_unique_id(&self) -> i32 {
@@ -71,6 +148,31 @@ impl Rankable for NewParticipant {
     fn get_elo(&self) -> f64 {
         self.elo
     }
+
+    fn get_deviation(&self) -> f64 {
+        self.deviation
+    }
+
+    fn get_volatility(&self) -> f64 {
+        self.volatility
+    }
+
+    fn get_games_played(&self) -> i32 {
+        self.games_played
+    }
+
+    fn clone_with_rating(&self, rating: Rating) -> Self {
+        NewParticipant {
+            deck_id: self.deck_id,
+            game_id: self.game_id,
+            win: self.win,
+            elo: rating.elo,
+            deviation: rating.deviation,
+            volatility: rating.volatility,
+            games_played: self.games_played,
+            previous_elo: self.previous_elo,
+        }
+    }
 }
 
 impl<T> Elo for T
@@ -78,6 +180,10 @@ where
     T: Rankable,
 {
     fn compute_elo(entities: &Vec<T>) -> Vec<T> {
+        T::compute_elo_with(entities, &K_FACTOR_SCHEDULE)
+    }
+
+    fn compute_elo_with(entities: &Vec<T>, schedule: &KFactorSchedule) -> Vec<T> {
         let mut transactions = HashMap::new();
         let win_count = entities
             .into_iter()
@@ -93,20 +199,11 @@ where
                             transformed_rating(opponent.get_elo()),
                         );
 
-                        let i_win = i.get_win();
                         let i_elo = i.get_elo();
-
-                        let opponent_win = opponent.get_win();
-
-                        // We both won call it a draw
-                        let new_elo = if i_win && opponent_win {
-                            elo_rating(i_elo, GameOutcome::DRAW, expected)
-                        } else if i_win && !opponent_win {
-                            elo_rating(i_elo, GameOutcome::WIN, expected)
-                        } else if !i_win && opponent_win {
-                            elo_rating(i_elo, GameOutcome::LOSE, expected)
-                        } else {
-                            i_elo
+                        let k = schedule.k_factor(i.get_games_played(), i_elo);
+                        let new_elo = match outcome_between(i.get_win(), opponent.get_win()) {
+                            Some(outcome) => elo_rating(i_elo, outcome, expected, k),
+                            None => i_elo,
                         };
 
                         let entry = transactions.entry(i.get_unique_id()).or_insert(0.0);
@@ -130,6 +227,118 @@ where
     }
 }
 
+impl KFactorSchedule {
+    pub fn from_env() -> KFactorSchedule {
+        let k = config::env_or("ELO_K", DEFAULT_IMPACT);
+        let bands = config::env_or("ELO_K_BANDS", String::new());
+
+        let schedule = KFactorSchedule {
+            provisional_games: config::env_or("ELO_PROVISIONAL_GAMES", 0),
+            provisional_k: config::env_or("ELO_PROVISIONAL_K", k),
+            k: k,
+            minimum_k: config::env_or("ELO_MINIMUM_K", k),
+            taper_games: config::env_or("ELO_TAPER_GAMES", 0),
+            bands: parse_bands(&bands)
+                .unwrap_or_else(|_| panic!("ELO_K_BANDS has an invalid value: {}", bands)),
+        };
+        let problems = schedule.problems();
+        if !problems.is_empty() {
+            panic!("{}", problems.join(", "));
+        }
+        schedule
+    }
+
+    /// What is wrong with the settings, a K below zero would move ratings the wrong way
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.k < 0.0 {
+            problems.push(format!("ELO_K ({}) must not be negative", self.k));
+        }
+        if self.provisional_k < 0.0 {
+            problems.push(format!(
+                "ELO_PROVISIONAL_K ({}) must not be negative",
+                self.provisional_k
+            ));
+        }
+        if self.minimum_k < 0.0 || self.minimum_k > self.k {
+            problems.push(format!(
+                "ELO_MINIMUM_K ({}) must be between 0 and ELO_K ({})",
+                self.minimum_k, self.k
+            ));
+        }
+        if self.provisional_games < 0 {
+            problems.push(format!(
+                "ELO_PROVISIONAL_GAMES ({}) must not be negative",
+                self.provisional_games
+            ));
+        }
+        if self.taper_games < 0 {
+            problems.push(format!(
+                "ELO_TAPER_GAMES ({}) must not be negative",
+                self.taper_games
+            ));
+        }
+        if self.bands.iter().any(|band| band.k < 0.0) {
+            problems.push("ELO_K_BANDS must not have a negative K".to_string());
+        }
+        problems
+    }
+
+    pub fn k_factor(&self, games_played: i32, rating: f64) -> f64 {
+        if games_played < self.provisional_games {
+            return self.provisional_k;
+        }
+
+        let established_games = games_played - self.provisional_games;
+        let k = if self.taper_games > 0 {
+            let progress = (established_games as f64 / self.taper_games as f64).min(1.0);
+            self.k - (self.k - self.minimum_k) * progress
+        } else {
+            self.k
+        };
+
+        self.bands
+            .iter()
+            .filter(|band| rating >= band.min_rating)
+            .map(|band| band.k)
+            .fold(k, f64::min)
+    }
+}
+
+impl Default for KFactorSchedule {
+    fn default() -> Self {
+        KFactorSchedule {
+            provisional_games: 0,
+            provisional_k: DEFAULT_IMPACT,
+            k: DEFAULT_IMPACT,
+            minimum_k: DEFAULT_IMPACT,
+            taper_games: 0,
+            bands: vec![],
+        }
+    }
+}
+
+///
+/// Parses bands written as comma separated `rating:k` pairs,
+/// eg. `1200:32,1400:24`
+///
+fn parse_bands(value: &str) -> Result<Vec<RatingBand>, ()> {
+    value
+        .split(',')
+        .map(|band| band.trim())
+        .filter(|band| !band.is_empty())
+        .map(|band| {
+            let mut parts = band.splitn(2, ':');
+            let min_rating = parts.next().and_then(|it| it.trim().parse().ok());
+            let k = parts.next().and_then(|it| it.trim().parse().ok());
+            match (min_rating, k) {
+                (Some(min_rating), Some(k)) => Ok(RatingBand { min_rating, k }),
+                _ => Err(()),
+            }
+        })
+        .collect()
+}
+
 impl From<GameOutcome> for f64 {
     fn from(outcome: GameOutcome) -> Self {
         match outcome {
@@ -140,6 +349,20 @@ impl From<GameOutcome> for f64 {
     }
 }
 
+///
+/// The result of a single pairing inside a multiplayer game. Two winners
+/// are treated as a draw and two losers didn't decide anything between
+/// themselves, so that pairing is skipped entirely.
+///
+pub fn outcome_between(win: bool, opponent_win: bool) -> Option<GameOutcome> {
+    match (win, opponent_win) {
+        (true, true) => Some(GameOutcome::DRAW),
+        (true, false) => Some(GameOutcome::WIN),
+        (false, true) => Some(GameOutcome::LOSE),
+        (false, false) => None,
+    }
+}
+
 fn expected_score(r1: f64, r2: f64) -> f64 {
     if r1 == 0.0 && r2 == 0.0 {
         0.0
@@ -152,8 +375,8 @@ fn transformed_rating(rating: f64) -> f64 {
     (10.0_f64).powf(rating / 400.0)
 }
 
-fn elo_rating(current_rating: f64, outcome: GameOutcome, expected_score: f64) -> f64 {
-    current_rating + DEFAULT_IMPACT * (f64::from(outcome) - expected_score)
+fn elo_rating(current_rating: f64, outcome: GameOutcome, expected_score: f64, k: f64) -> f64 {
+    current_rating + k * (f64::from(outcome) - expected_score)
 }
 
 #[cfg(test)]
@@ -164,17 +387,32 @@ mod tests {
     #[test]
     fn test_elo_rating() {
         assert_eq! {
-            elo_rating(1000.0, GameOutcome::WIN, expected_score(1000.0, 1000.0)),
+            elo_rating(
+                1000.0,
+                GameOutcome::WIN,
+                expected_score(1000.0, 1000.0),
+                DEFAULT_IMPACT
+            ),
             1020.0
         }
 
         assert_eq! {
-            elo_rating(1000.0, GameOutcome::LOSE, expected_score(1000.0, 1000.0)),
+            elo_rating(
+                1000.0,
+                GameOutcome::LOSE,
+                expected_score(1000.0, 1000.0),
+                DEFAULT_IMPACT
+            ),
             980.0
         }
 
         assert_eq! {
-            elo_rating(1000.0, GameOutcome::DRAW, expected_score(1000.0, 1000.0)),
+            elo_rating(
+                1000.0,
+                GameOutcome::DRAW,
+                expected_score(1000.0, 1000.0),
+                DEFAULT_IMPACT
+            ),
             1000.0
         }
     }
@@ -314,4 +552,111 @@ mod tests {
         assert_eq!(result[3].elo, 963.8712365796924);
         assert_eq!(result[4].elo, 942.7775878108375);
     }
+
+    fn tapering_schedule() -> KFactorSchedule {
+        KFactorSchedule {
+            provisional_games: 10,
+            provisional_k: 80.0,
+            k: 40.0,
+            minimum_k: 20.0,
+            taper_games: 20,
+            bands: vec![
+                RatingBand {
+                    min_rating: 1200.0,
+                    k: 32.0,
+                },
+                RatingBand {
+                    min_rating: 1400.0,
+                    k: 16.0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_k_factor_defaults_to_default_impact() {
+        let schedule = KFactorSchedule::default();
+
+        assert_eq!(schedule.k_factor(0, 1000.0), DEFAULT_IMPACT);
+        assert_eq!(schedule.k_factor(300, 1600.0), DEFAULT_IMPACT);
+    }
+
+    #[test]
+    fn test_k_factor_schedule() {
+        let schedule = tapering_schedule();
+
+        assert_eq!(schedule.k_factor(0, 1000.0), 80.0);
+        assert_eq!(schedule.k_factor(9, 1000.0), 80.0);
+        assert_eq!(schedule.k_factor(10, 1000.0), 40.0);
+        assert_eq!(schedule.k_factor(20, 1000.0), 30.0);
+        assert_eq!(schedule.k_factor(30, 1000.0), 20.0);
+        assert_eq!(schedule.k_factor(300, 1000.0), 20.0);
+    }
+
+    #[test]
+    fn test_k_factor_rating_bands() {
+        let schedule = tapering_schedule();
+
+        assert_eq!(schedule.k_factor(5, 1500.0), 80.0);
+        assert_eq!(schedule.k_factor(10, 1250.0), 32.0);
+        assert_eq!(schedule.k_factor(10, 1400.0), 16.0);
+        assert_eq!(schedule.k_factor(300, 1250.0), 20.0);
+    }
+
+    #[test]
+    fn test_k_factor_schedule_problems() {
+        assert!(KFactorSchedule::default().problems().is_empty());
+        assert!(tapering_schedule().problems().is_empty());
+        let negative = KFactorSchedule {
+            provisional_games: -1,
+            provisional_k: -1.0,
+            k: -1.0,
+            minimum_k: -1.0,
+            taper_games: -1,
+            bands: vec![RatingBand {
+                min_rating: 1200.0,
+                k: -1.0,
+            }],
+        };
+        assert_eq!(negative.problems().len(), 6);
+        let inverted = KFactorSchedule {
+            minimum_k: 60.0,
+            ..tapering_schedule()
+        };
+        assert_eq!(inverted.problems().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_bands() {
+        assert_eq!(parse_bands(""), Ok(vec![]));
+        assert_eq!(
+            parse_bands("1200:32, 1400:24"),
+            Ok(vec![
+                RatingBand {
+                    min_rating: 1200.0,
+                    k: 32.0,
+                },
+                RatingBand {
+                    min_rating: 1400.0,
+                    k: 24.0,
+                },
+            ])
+        );
+        assert_eq!(parse_bands("1200"), Err(()));
+        assert_eq!(parse_bands("1200:fast"), Err(()));
+    }
+
+    #[test]
+    fn test_compute_elo_with_provisional_participant() {
+        let mut established = NewParticipant::new(0, 2, false, 1000.0);
+        established.games_played = 50;
+        let test_case = vec![NewParticipant::new(0, 1, true, 1000.0), established];
+
+        let result = NewParticipant::compute_elo_with(&test_case, &tapering_schedule());
+
+        assert_eq!(result[0].elo, 1040.0);
+        assert_eq!(result[1].elo, 990.0);
+        assert_eq!(result[0].games_played, 0);
+        assert_eq!(result[1].games_played, 50);
+    }
 }
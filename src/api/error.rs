@@ -1,5 +1,6 @@
+use argon2::Error as Argon2Error;
 use bcrypt::BcryptError;
-use diesel::result::Error as DieselError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use jsonwebtoken::errors::Error as JwtError;
 use rocket::http::ContentType;
 use rocket::http::Status;
@@ -10,23 +11,68 @@ use std::convert::From;
 
 #[derive(Debug)]
 pub enum ApiError {
-    NotFound,
     BadRequest,
-    InternalServerError,
     Unauthorized,
+    Forbidden,
+    /// The player has to verify their email before they can log in
+    EmailNotVerified,
+    /// The player registered but an admin hasn't approved them yet
+    PendingApproval,
+    NotFound,
+    Conflict(Vec<FieldError>),
+    Validation(Vec<FieldError>),
+    /// Carries the number of seconds the client should wait before retrying
+    RateLimited(u64),
+    InternalServerError,
 }
 
+///
+/// `error` is a stable machine readable code that clients can match on,
+/// `message` is only meant for humans and may change.
+///
 #[derive(Serialize)]
 pub struct ErrorResponse {
     code: u16,
+    error: &'static str,
     message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            ApiError::BadRequest => "bad_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::EmailNotVerified => "email_not_verified",
+            ApiError::PendingApproval => "pending_approval",
+            ApiError::NotFound => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::InternalServerError => "internal_error",
+        }
+    }
 }
 
 impl From<DieselError> for ApiError {
     fn from(e: DieselError) -> Self {
         match e {
             DieselError::NotFound => ApiError::NotFound,
-            _ => ApiError::InternalServerError,
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => {
+                ApiError::Conflict(unique_violation_fields(info.message()))
+            }
+            _ => {
+                error!("Database error: {:?}", e);
+                ApiError::InternalServerError
+            }
         }
     }
 }
@@ -39,6 +85,13 @@ impl From<BcryptError> for ApiError {
     }
 }
 
+impl From<Argon2Error> for ApiError {
+    fn from(e: Argon2Error) -> Self {
+        error!("Password hashing error: {:?}", e);
+        ApiError::InternalServerError
+    }
+}
+
 impl From<JwtError> for ApiError {
     fn from(e: JwtError) -> Self {
         match e {
@@ -47,13 +100,19 @@ impl From<JwtError> for ApiError {
     }
 }
 
-impl From<ApiError> for Status {
-    fn from(e: ApiError) -> Self {
-        match e {
-            ApiError::NotFound => Status::NotFound,
+impl<'a> From<&'a ApiError> for Status {
+    fn from(e: &ApiError) -> Self {
+        match *e {
             ApiError::BadRequest => Status::BadRequest,
-            ApiError::InternalServerError => Status::InternalServerError,
             ApiError::Unauthorized => Status::Unauthorized,
+            ApiError::Forbidden | ApiError::EmailNotVerified | ApiError::PendingApproval => {
+                Status::Forbidden
+            }
+            ApiError::NotFound => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Validation(_) => Status::UnprocessableEntity,
+            ApiError::RateLimited(_) => Status::TooManyRequests,
+            ApiError::InternalServerError => Status::InternalServerError,
         }
     }
 }
@@ -62,26 +121,90 @@ impl From<Status> for ErrorResponse {
     fn from(status: Status) -> Self {
         ErrorResponse {
             code: status.code,
+            error: status_error_code(status),
             message: status.reason.to_string(),
+            errors: vec![],
+        }
+    }
+}
+
+impl From<ApiError> for ErrorResponse {
+    fn from(e: ApiError) -> Self {
+        let status = Status::from(&e);
+        let error = e.error_code();
+        let errors = match e {
+            ApiError::Conflict(errors) | ApiError::Validation(errors) => errors,
+            _ => vec![],
+        };
+
+        ErrorResponse {
+            error,
+            errors,
+            ..ErrorResponse::from(status)
         }
     }
 }
 
 impl<'r> Responder<'r> for ApiError {
     fn respond_to(self, req: &Request) -> Result<Response<'r>, Status> {
-        let status = Status::from(self);
-        let json_body = Json(ErrorResponse::from(status));
+        let status = Status::from(&self);
+        let retry_after = match self {
+            ApiError::RateLimited(seconds) => Some(seconds),
+            _ => None,
+        };
+        let json_body = Json(ErrorResponse::from(self));
 
         match json_body.respond_to(req) {
-            Ok(json_response) => Ok(Response::build_from(json_response)
-                .status(status)
-                .header(ContentType::JSON)
-                .finalize()),
+            Ok(json_response) => {
+                let mut response = Response::build_from(json_response);
+                response.status(status).header(ContentType::JSON);
+                if let Some(seconds) = retry_after {
+                    response.raw_header("Retry-After", seconds.to_string());
+                }
+                Ok(response.finalize())
+            }
             Err(_) => Err(Status::InternalServerError),
         }
     }
 }
 
+///
+/// Codes for errors that never made it to a route, eg. a request guard
+/// failing or a body that isn't valid JSON.
+///
+fn status_error_code(status: Status) -> &'static str {
+    match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        403 => "forbidden",
+        404 => "not_found",
+        409 => "conflict",
+        422 => "malformed_body",
+        429 => "rate_limited",
+        503 => "service_unavailable",
+        _ => "internal_error",
+    }
+}
+
+///
+/// SQLite reports unique violations as
+/// `UNIQUE constraint failed: player.email, player.alias`
+///
+fn unique_violation_fields(message: &str) -> Vec<FieldError> {
+    let columns = match message.find(": ") {
+        Some(index) => &message[index + 2..],
+        None => return vec![],
+    };
+
+    columns
+        .split(", ")
+        .map(|column| FieldError {
+            field: column.rsplit('.').next().unwrap_or(column).to_string(),
+            message: "is already taken".to_string(),
+        })
+        .collect()
+}
+
 ///
 ///  These catchers are needed in order to provide a custom
 ///  error response for request guards. Ideally we should be
@@ -98,7 +221,49 @@ pub fn handle_401(_: &Request) -> Json<ErrorResponse> {
     Json(ErrorResponse::from(Status::Unauthorized))
 }
 
+#[catch(403)]
+pub fn handle_403(_: &Request) -> Json<ErrorResponse> {
+    Json(ErrorResponse::from(Status::Forbidden))
+}
+
 #[catch(404)]
 pub fn handle_404(_: &Request) -> Json<ErrorResponse> {
     Json(ErrorResponse::from(Status::NotFound))
 }
+
+#[catch(422)]
+pub fn handle_422(_: &Request) -> Json<ErrorResponse> {
+    Json(ErrorResponse::from(Status::UnprocessableEntity))
+}
+
+#[catch(500)]
+pub fn handle_500(_: &Request) -> Json<ErrorResponse> {
+    Json(ErrorResponse::from(Status::InternalServerError))
+}
+
+#[catch(503)]
+pub fn handle_503(_: &Request) -> Json<ErrorResponse> {
+    Json(ErrorResponse::from(Status::ServiceUnavailable))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_unique_violation_fields() {
+        assert_eq!(
+            unique_violation_fields("UNIQUE constraint failed: player.email"),
+            vec![FieldError {
+                field: "email".to_string(),
+                message: "is already taken".to_string(),
+            }]
+        );
+        assert_eq!(
+            unique_violation_fields("UNIQUE constraint failed: deck.alias, deck.player_id").len(),
+            2
+        );
+        assert_eq!(unique_violation_fields("constraint failed"), vec![]);
+    }
+}
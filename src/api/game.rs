@@ -1,20 +1,31 @@
-use api::auth::ApiToken;
-use api::common::PaginatedResponse;
-use api::elo::Elo;
+use api::auth::{ApiToken, GameSubmitterToken, MemberToken};
+use api::common::{check_page, current_time, PaginatedResponse, DEFAULT_LIMIT, DEFAULT_OFFSET};
+use api::elo::Rankable;
 use api::error::ApiError;
+use api::league::{authorize_league_member, is_league_admin};
+use api::rating::{Rating, RATING_ENGINE};
+use api::season::{season_starting_between, soft_reset};
+use api::validation::{GameRules, Validator, GAME_RULES};
+use base64;
 use db::DbConn;
-use models::game::{Game, NewGame};
+use diesel::prelude::*;
+use models::deck::Deck;
+use models::game::{Game, GameFilter, NewGame};
+use models::league::{League, LeagueMember};
 use models::participant::{NewParticipant, Participant};
+use models::season::Season;
+use rocket::http::uri::Uri;
 use rocket::request::Form;
 use rocket_contrib::json::Json;
+use std::collections::{HashMap, HashSet};
 
 pub const DEFAULT_ELO: f64 = 1000.0;
-pub const DEFAULT_LIMIT: i32 = 25;
-pub const DEFAULT_OFFSET: i32 = 0;
 
 #[derive(Deserialize)]
 pub struct GameRequest {
     timestamp: Option<i32>,
+    /// Records the game in a league rather than the shared pool
+    league_id: Option<i32>,
     participants: Vec<ParticipantRequest>,
 }
 
@@ -24,16 +35,30 @@ pub struct EditGameRequest {
     participants: Vec<ParticipantRequest>,
 }
 
-#[derive(FromForm, Debug)]
+#[derive(FromForm, Default, Debug)]
 pub struct GameRequestParams {
     limit: Option<i32>,
     offset: Option<i32>,
+    /// Continue after the last game of a previous page, see `next`
+    cursor: Option<String>,
+    player_id: Option<i32>,
+    deck_id: Option<i32>,
+    commander: Option<String>,
+    winner: Option<i32>,
+    /// Unix timestamps, both inclusive
+    from: Option<i64>,
+    to: Option<i64>,
+    /// Without it only the games played outside of any league are listed
+    league_id: Option<i32>,
+    /// Asks for the games outside of any league, which is also the default
+    pool: Option<bool>,
 }
 
 #[derive(Serialize)]
 pub struct GameResponse {
     pub id: i32,
     pub time_stamp: f64,
+    pub league_id: Option<i32>,
     pub participants: Vec<ParticipantResponse>,
 }
 
@@ -50,50 +75,191 @@ pub struct ParticipantResponse {
     previous_elo: f64,
 }
 
+impl GameResponse {
+    fn new(game: Game, participants: Vec<Participant>) -> GameResponse {
+        GameResponse {
+            id: game.id,
+            time_stamp: game.time_stamp,
+            league_id: game.league_id,
+            participants: participants
+                .into_iter()
+                .map(|participant| ParticipantResponse {
+                    deck_id: participant.deck_id,
+                    elo: participant.elo,
+                    previous_elo: participant.previous_elo,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl GameRequest {
+    fn validate(&self, conn: &SqliteConnection) -> Result<(), ApiError> {
+        let mut validator = Validator::new();
+        if let Some(timestamp) = self.timestamp {
+            validator.check(timestamp >= 0, "timestamp", "must not be negative");
+            validator.check(
+                timestamp as i64 <= current_time(),
+                "timestamp",
+                "must not be in the future",
+            );
+        }
+        // Membership is only worth checking in a league that exists
+        let league_id = match self.league_id {
+            Some(league_id) => {
+                let league = League::find_by_id(league_id, conn).optional()?;
+                validator.check(
+                    league.is_some(),
+                    "league_id",
+                    format!("league {} does not exist", league_id),
+                );
+                league.map(|league| league.id)
+            }
+            None => None,
+        };
+        validate_participants(&mut validator, &self.participants, league_id, &[], conn)?;
+        validator.finish()
+    }
+}
+
+impl GameRequestParams {
+    /// Checks the parameters and returns the decoded cursor, if any
+    fn validate(&self) -> Result<Option<(f64, i32)>, ApiError> {
+        let mut validator = Validator::new();
+        check_page(&mut validator, self.limit, self.offset);
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            validator.check(from <= to, "to", "must not be before from");
+        }
+        match self.pool {
+            Some(true) => validator.check(
+                self.league_id.is_none(),
+                "pool",
+                "can't be combined with league_id",
+            ),
+            Some(false) => validator.check(
+                self.league_id.is_some(),
+                "pool",
+                "needs a league_id to list instead",
+            ),
+            None => {}
+        }
+
+        let cursor = self.cursor.as_ref().and_then(|c| decode_cursor(c));
+        validator.check(
+            self.cursor.is_none() || cursor.is_some(),
+            "cursor",
+            "is invalid",
+        );
+        validator.finish()?;
+        Ok(cursor)
+    }
+
+    fn filter(&self) -> GameFilter {
+        GameFilter {
+            player_id: self.player_id,
+            deck_id: self.deck_id,
+            commander: self.commander.clone(),
+            winner: self.winner,
+            from: self.from.map(|from| from as f64),
+            to: self.to.map(|to| to as f64),
+            league_id: self.league_id,
+        }
+    }
+
+    ///
+    /// Link to the page after `cursor` with the same limit and filters. The
+    /// offset is left out since the cursor already marks where to continue.
+    ///
+    fn next_link(&self, limit: i32, cursor: &str) -> String {
+        let mut query = vec![format!("limit={}", limit), format!("cursor={}", cursor)];
+        if let Some(player_id) = self.player_id {
+            query.push(format!("player_id={}", player_id));
+        }
+        if let Some(deck_id) = self.deck_id {
+            query.push(format!("deck_id={}", deck_id));
+        }
+        if let Some(ref commander) = self.commander {
+            query.push(format!("commander={}", Uri::percent_encode(commander)));
+        }
+        if let Some(winner) = self.winner {
+            query.push(format!("winner={}", winner));
+        }
+        if let Some(from) = self.from {
+            query.push(format!("from={}", from));
+        }
+        if let Some(to) = self.to {
+            query.push(format!("to={}", to));
+        }
+        if let Some(league_id) = self.league_id {
+            query.push(format!("league_id={}", league_id));
+        }
+        if let Some(pool) = self.pool {
+            query.push(format!("pool={}", pool));
+        }
+        format!("/games?{}", query.join("&"))
+    }
+}
+
+impl EditGameRequest {
+    fn validate(
+        &self,
+        game: &Game,
+        current: &Vec<Participant>,
+        conn: &SqliteConnection,
+    ) -> Result<(), ApiError> {
+        let mut validator = Validator::new();
+        let current_decks = current.iter().map(|p| p.deck_id).collect::<Vec<_>>();
+        validate_participants(
+            &mut validator,
+            &self.participants,
+            game.league_id,
+            &current_decks,
+            conn,
+        )?;
+        validator.finish()
+    }
+}
+
 #[get("/?<params..>")]
 pub fn get_games(
     params: Form<GameRequestParams>,
     conn: DbConn,
     _token: ApiToken,
 ) -> Result<Json<PaginatedResponse<GameResponse>>, ApiError> {
-    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
-    let offset = params.offset.unwrap_or(DEFAULT_OFFSET);
-    fetch_games(limit, offset, conn)
+    fetch_games(&params, conn)
 }
 
 fn fetch_games(
-    limit: i32,
-    offset: i32,
+    params: &GameRequestParams,
     conn: DbConn,
 ) -> Result<Json<PaginatedResponse<GameResponse>>, ApiError> {
-    let mut response = vec![];
-    for game in Game::all(limit, offset, &conn)? {
-        let participants = Participant::find_by_game(&game, &conn)?;
-        let participant_response = participants
-            .into_iter()
-            .map(|participant| {
-                let previous_elo = participant
-                    .find_previous(&conn)
-                    .ok()
-                    .map_or(DEFAULT_ELO, |p| p.elo);
-                ParticipantResponse {
-                    deck_id: participant.deck_id,
-                    elo: participant.elo,
-                    previous_elo: previous_elo,
-                }
-            })
-            .collect();
+    let after = params.validate()?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = params.offset.unwrap_or(DEFAULT_OFFSET);
+    let filter = params.filter();
+
+    let total = Game::count(&filter, &conn)?;
+    // One extra game tells whether there is another page
+    let mut games = Game::filtered(&filter, after, limit as i64 + 1, offset as i64, &conn)?;
+    let next = if games.len() > limit as usize {
+        games.truncate(limit as usize);
+        games
+            .last()
+            .map(|last| params.next_link(limit, &encode_cursor(last)))
+    } else {
+        None
+    };
 
-        response.push(GameResponse {
-            id: game.id,
-            time_stamp: game.time_stamp,
-            participants: participant_response,
-        });
-    }
+    let response = Participant::all_grouped_by_game(games, &conn)?
+        .into_iter()
+        .map(|(game, participants)| GameResponse::new(game, participants))
+        .collect();
 
     Ok(Json(PaginatedResponse {
         limit,
         offset,
+        total,
+        next,
         data: response,
     }))
 }
@@ -102,157 +268,651 @@ fn fetch_games(
 pub fn get_game(id: i32, conn: DbConn, _token: ApiToken) -> Result<Json<GameResponse>, ApiError> {
     let game = Game::find_by_id(id, &conn)?;
     let participants = Participant::find_by_game(&game, &conn)?;
-    let participant_response = participants
-        .into_iter()
-        .map(|participant| {
-            let previous_elo = participant
-                .find_previous(&conn)
-                .ok()
-                .map_or(DEFAULT_ELO, |p| p.elo);
-            ParticipantResponse {
-                deck_id: participant.deck_id,
-                elo: participant.elo,
-                previous_elo: previous_elo,
-            }
-        })
-        .collect();
-
-    let response = GameResponse {
-        id: game.id,
-        time_stamp: game.time_stamp,
-        participants: participant_response,
-    };
-
-    Ok(Json(response))
+    Ok(Json(GameResponse::new(game, participants)))
 }
 
 #[post("/", format = "application/json", data = "<req>")]
 pub fn create_game(
     req: Json<GameRequest>,
     conn: DbConn,
-    _token: ApiToken,
+    token: GameSubmitterToken,
 ) -> Result<Json<GameResponse>, ApiError> {
     let game_request = req.into_inner();
-    let new_game = NewGame::insert(NewGame::new(&game_request.timestamp), &conn)?;
-
-    let new_participants = game_request
+    game_request.validate(&conn)?;
+    if let Some(league_id) = game_request.league_id {
+        authorize_league_member(&token.0, league_id, &conn)?;
+    }
+    let deck_ids = game_request
         .participants
-        .into_iter()
-        .map(|x| {
-            let current_elo: f64 = Participant::find_latest_by_deck_id(x.deck_id, &conn)
-                .map(|p| p.elo)
-                .unwrap_or(DEFAULT_ELO);
-
-            NewParticipant {
-                game_id: new_game.id,
-                deck_id: x.deck_id,
-                win: x.win,
-                elo: current_elo,
-            }
-        })
+        .iter()
+        .map(|p| p.deck_id)
         .collect();
+    authorize_game_change(&token.0, game_request.league_id, &deck_ids, &conn)?;
 
-    let updated = NewParticipant::compute_elo(&new_participants);
-    let participants = NewParticipant::insert(&updated, &conn)?;
-
-    let rankings = participants
-        .into_iter()
-        .map(|ranking| {
-            let previous_elo = ranking
-                .find_previous(&conn)
-                .ok()
-                .map_or(DEFAULT_ELO, |p| p.elo);
-            ParticipantResponse {
-                deck_id: ranking.deck_id,
-                elo: ranking.elo,
-                previous_elo: previous_elo,
-            }
-        })
-        .collect();
+    let (new_game, participants) = conn.transaction::<_, ApiError, _>(|| {
+        let new_game = NewGame::new(&game_request.timestamp, game_request.league_id);
+        let new_game = NewGame::insert(new_game, &conn)?;
+        let rated = rate_participants(&new_game, &game_request.participants, &conn)?;
+        let participants = NewParticipant::insert(&rated, &conn)?;
 
-    let response = GameResponse {
-        id: new_game.id,
-        time_stamp: new_game.time_stamp,
-        participants: rankings,
-    };
+        // A backdated game changes the starting point of every game played after it
+        refresh_elo_after(&new_game, &conn)?;
+        Ok((new_game, participants))
+    })?;
 
-    Ok(Json(response))
+    Ok(Json(GameResponse::new(new_game, participants)))
 }
 
 #[delete("/<id>")]
 pub fn delete_game(
     id: i32,
     conn: DbConn,
-    _token: ApiToken,
+    token: MemberToken,
 ) -> Result<Json<PaginatedResponse<GameResponse>>, ApiError> {
-    let game = Game::find_by_id(id, &conn)?;
-    let participants = Participant::find_by_game(&game, &conn)?;
+    conn.transaction::<_, ApiError, _>(|| {
+        let game = Game::find_by_id(id, &conn)?;
+        let participants = Participant::find_by_game(&game, &conn)?;
+        let deck_ids = participants.iter().map(|p| p.deck_id).collect::<Vec<_>>();
+        authorize_game_change(&token.0, game.league_id, &deck_ids, &conn)?;
 
-    Participant::delete_all(participants, &conn);
-    let _ = game.delete(&conn);
-    let _ = refresh_elo_after(game, &conn)?;
+        Participant::delete_all(participants, &conn)?;
+        game.delete(&conn)?;
+        refresh_elo_after(&game, &conn)
+    })?;
 
-    fetch_games(DEFAULT_LIMIT, DEFAULT_OFFSET, conn)
+    fetch_games(&GameRequestParams::default(), conn)
 }
 
 #[put("/", format = "application/json", data = "<req>")]
 pub fn update_game(
     req: Json<EditGameRequest>,
     conn: DbConn,
-    _token: ApiToken,
+    token: MemberToken,
 ) -> Result<Json<GameResponse>, ApiError> {
     let request = req.into_inner();
-    let game = Game::find_by_id(request.id, &conn)?;
-
-    let mut new_participants = vec![];
-    for p in request.participants {
-        let latest_elo_before_game =
-            Participant::latest_by_deck_id_before_game(p.deck_id, &game, &conn)
-                .map(|p| p.elo)
-                .unwrap_or(DEFAULT_ELO);
-
-        let new_p = NewParticipant {
-            game_id: game.id,
-            deck_id: p.deck_id,
-            win: p.win,
-            elo: latest_elo_before_game,
-        };
-        new_participants.push(new_p)
+
+    conn.transaction::<_, ApiError, _>(|| {
+        let game = Game::find_by_id(request.id, &conn)?;
+        let participants = Participant::find_by_game(&game, &conn)?;
+        let deck_ids = participants.iter().map(|p| p.deck_id).collect::<Vec<_>>();
+        authorize_game_change(&token.0, game.league_id, &deck_ids, &conn)?;
+        request.validate(&game, &participants, &conn)?;
+        let new_deck_ids = request.participants.iter().map(|p| p.deck_id).collect();
+        authorize_game_change(&token.0, game.league_id, &new_deck_ids, &conn)?;
+
+        let rated = rate_participants(&game, &request.participants, &conn)?;
+        Participant::delete_all(participants, &conn)?;
+        NewParticipant::insert(&rated, &conn)?;
+
+        refresh_elo_after(&game, &conn)
+    })?;
+
+    get_game(request.id, conn, token.0)
+}
+
+///
+/// Only admins, the admins of the league a game is played in and players
+/// with one of `deck_ids` in the game may record it or change it. Edits are
+/// checked against both the old and the new decks, so nobody can swap in
+/// a pod of other players' decks.
+///
+fn authorize_game_change(
+    token: &ApiToken,
+    league_id: Option<i32>,
+    deck_ids: &Vec<i32>,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    if token.is_admin() {
+        return Ok(());
+    }
+    if let Some(league_id) = league_id {
+        if is_league_admin(token, league_id, conn)? {
+            return Ok(());
+        }
     }
 
-    let participants = Participant::find_by_game(&game, &conn)?;
-    Participant::delete_all(participants, &conn);
-    let _ = NewParticipant::insert(&new_participants, &conn);
+    let is_participant = Deck::find_by_ids(&deck_ids, conn)?
+        .iter()
+        .any(|deck| deck.player_id == token.player_id());
 
-    let previous_game = game.find_previous(&conn).unwrap_or(game);
-    let _ = refresh_elo_after(previous_game, &conn)?;
+    if is_participant {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+///
+/// Checks the pod size against `GAME_RULES` and makes sure every deck exists,
+/// only appears once, is still active and, in a league, belongs to one of its
+/// members. Decks that were already part of a game that is being edited are
+/// allowed even if they have since been retired or their player has left.
+///
+fn validate_participants(
+    validator: &mut Validator,
+    participants: &Vec<ParticipantRequest>,
+    league_id: Option<i32>,
+    current: &[i32],
+    conn: &SqliteConnection,
+) -> QueryResult<()> {
+    let deck_ids = participants.iter().map(|p| p.deck_id).collect::<Vec<_>>();
+    let decks = Deck::find_by_ids(&deck_ids, conn)?
+        .into_iter()
+        .map(|deck| (deck.id, deck))
+        .collect::<HashMap<_, _>>();
+    let members = match league_id {
+        Some(league_id) => Some(LeagueMember::player_ids(league_id, conn)?),
+        None => None,
+    };
 
-    get_game(request.id, conn, _token)
+    check_participants(
+        validator,
+        participants,
+        &decks,
+        members.as_ref(),
+        current,
+        *GAME_RULES,
+    );
+    Ok(())
 }
 
-fn refresh_elo_after(game: Game, conn: &DbConn) -> Result<(), ApiError> {
-    let next_games = game.all_after(&conn)?;
-    for g in next_games {
-        let parts_with_previous_elo = Participant::find_by_game(&g, &conn)?
-            .into_iter()
-            .map(|_p| {
-                let previous_elo = _p
-                    .find_previous(&conn)
-                    .map(|it| it.elo)
-                    .unwrap_or(DEFAULT_ELO);
+///
+/// The checks behind `validate_participants`, given the decks that were
+/// asked for and the players in the game's league, if it has one.
+///
+fn check_participants(
+    validator: &mut Validator,
+    participants: &Vec<ParticipantRequest>,
+    decks: &HashMap<i32, Deck>,
+    members: Option<&HashSet<i32>>,
+    current: &[i32],
+    rules: GameRules,
+) {
+    validator.check(
+        participants.len() >= rules.min_participants,
+        "participants",
+        format!(
+            "a game needs at least {} participants",
+            rules.min_participants
+        ),
+    );
+    validator.check(
+        participants.len() <= rules.max_participants,
+        "participants",
+        format!(
+            "a game can have at most {} participants",
+            rules.max_participants
+        ),
+    );
+
+    let mut seen = HashSet::new();
+    for (index, participant) in participants.iter().enumerate() {
+        let field = format!("participants[{}].deck_id", index);
+        let duplicate = !seen.insert(participant.deck_id);
+
+        match decks.get(&participant.deck_id) {
+            _ if duplicate => validator.error(
+                field,
+                format!("deck {} appears more than once", participant.deck_id),
+            ),
+            None => validator.error(
+                field,
+                format!("deck {} does not exist", participant.deck_id),
+            ),
+            Some(deck) if !deck.active && !current.contains(&deck.id) => {
+                validator.error(field, format!("deck {} has been retired", deck.id))
+            }
+            Some(deck)
+                if !current.contains(&deck.id)
+                    && members.map_or(false, |members| !members.contains(&deck.player_id)) =>
+            {
+                validator.error(
+                    field,
+                    format!("deck {} belongs to a player outside the league", deck.id),
+                )
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+///
+/// Seeds every participant with the rating its deck had going into `game`
+/// and rates the game with the current engine.
+///
+fn rate_participants(
+    game: &Game,
+    requests: &Vec<ParticipantRequest>,
+    conn: &SqliteConnection,
+) -> Result<Vec<NewParticipant>, ApiError> {
+    let deck_ids = requests.iter().map(|p| p.deck_id).collect::<Vec<_>>();
+    let previous = Participant::latest_by_deck_ids_before_game(&deck_ids, game, conn)?;
+    let seasons = seasons_for(game.league_id, conn)?;
+
+    let new_participants = requests
+        .iter()
+        .map(|p| {
+            let (rating, games_played) =
+                starting_state(previous.get(&p.deck_id), game.time_stamp, &seasons);
+            NewParticipant {
+                game_id: game.id,
+                deck_id: p.deck_id,
+                win: p.win,
+                elo: rating.elo,
+                deviation: rating.deviation,
+                volatility: rating.volatility,
+                games_played: games_played,
+                previous_elo: rating.elo,
+            }
+        })
+        .collect();
+
+    Ok(RATING_ENGINE.compute(&new_participants))
+}
+
+fn refresh_elo_after(game: &Game, conn: &SqliteConnection) -> Result<(), ApiError> {
+    let next_games = game.all_after(conn)?;
+    let first = match next_games.first() {
+        Some(first) => first,
+        None => return Ok({}),
+    };
+
+    let participants = Participant::all_after_game(game, conn)?;
+    let deck_ids = participants
+        .iter()
+        .map(|p| p.deck_id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let latest_by_deck = Participant::latest_by_deck_ids_before_game(&deck_ids, first, conn)?;
+
+    replay(
+        game.league_id,
+        &next_games,
+        participants,
+        latest_by_deck,
+        conn,
+    )?;
+    Ok({})
+}
+
+///
+/// Replays every game in order starting from `DEFAULT_ELO`, using the
+/// current rating engine and settings. Every league and the games played
+/// outside of them are replayed on their own. This runs in a single
+/// transaction and returns the number of participants whose stored rating
+/// changed.
+///
+pub fn recompute_ratings(conn: &SqliteConnection) -> Result<usize, ApiError> {
+    conn.transaction::<_, ApiError, _>(|| {
+        let games = Game::all_chronological(conn)?;
+        let participants = Participant::all(conn)?;
+
+        let mut updated = 0;
+        for (league_id, (games, participants)) in group_by_league(games, participants) {
+            updated += replay(league_id, &games, participants, HashMap::new(), conn)?;
+        }
+        Ok(updated)
+    })
+}
+
+///
+/// Splits games and their participants by the league they were played in,
+/// `None` being the games outside of any league. Games keep their order.
+///
+fn group_by_league(
+    games: Vec<Game>,
+    participants: Vec<Participant>,
+) -> HashMap<Option<i32>, (Vec<Game>, Vec<Participant>)> {
+    let league_of = games
+        .iter()
+        .map(|game| (game.id, game.league_id))
+        .collect::<HashMap<_, _>>();
+
+    let mut by_league = HashMap::new();
+    for game in games {
+        by_league
+            .entry(game.league_id)
+            .or_insert_with(|| (vec![], vec![]))
+            .0
+            .push(game);
+    }
+    for p in participants {
+        if let Some(league_id) = league_of.get(&p.game_id) {
+            if let Some(group) = by_league.get_mut(league_id) {
+                group.1.push(p);
+            }
+        }
+    }
+    by_league
+}
 
+///
+/// Rates `games`, which were all played in `league_id`, in order, seeding
+/// each deck from its entry in `latest_by_deck` the first time it shows up.
+/// Only participants whose stored state changed are written back, and their
+/// number is returned.
+///
+fn replay(
+    league_id: Option<i32>,
+    games: &Vec<Game>,
+    participants: Vec<Participant>,
+    mut latest_by_deck: HashMap<i32, (Participant, f64)>,
+    conn: &SqliteConnection,
+) -> QueryResult<usize> {
+    let seasons = seasons_for(league_id, conn)?;
+    let mut participants_by_game = HashMap::new();
+    for p in participants {
+        participants_by_game
+            .entry(p.game_id)
+            .or_insert_with(Vec::new)
+            .push(p);
+    }
+
+    let mut updated = 0;
+    for game in games {
+        let participants = participants_by_game.remove(&game.id).unwrap_or_default();
+        let seeded = participants
+            .iter()
+            .map(|p| {
+                let (rating, games_played) =
+                    starting_state(latest_by_deck.get(&p.deck_id), game.time_stamp, &seasons);
                 Participant {
-                    id: _p.id,
-                    game_id: _p.game_id,
-                    deck_id: _p.deck_id,
-                    win: _p.win,
-                    elo: previous_elo,
+                    games_played: games_played,
+                    previous_elo: rating.elo,
+                    ..p.clone_with_rating(rating)
                 }
             })
             .collect();
 
-        let updated = Participant::compute_elo(&parts_with_previous_elo);
-        Participant::update_all(&updated, &conn);
+        let rated = RATING_ENGINE.compute(&seeded);
+        for (before, after) in participants.iter().zip(rated.into_iter()) {
+            if before.get_rating() != after.get_rating()
+                || before.games_played != after.games_played
+                || before.previous_elo != after.previous_elo
+            {
+                Participant::update(&after, conn)?;
+                updated += 1;
+            }
+            latest_by_deck.insert(after.deck_id, (after, game.time_stamp));
+        }
+    }
+
+    Ok(updated)
+}
+
+/// Seasons only cover the games played outside of any league
+fn seasons_for(league_id: Option<i32>, conn: &SqliteConnection) -> QueryResult<Vec<Season>> {
+    match league_id {
+        Some(_) => Ok(vec![]),
+        None => Season::all(conn),
+    }
+}
+
+///
+/// The rating and number of games a deck brings into a game played at
+/// `time_stamp`, given its most recent participation before that game and
+/// when it was played. A season starting in between softens the rating.
+///
+fn starting_state(
+    previous: Option<&(Participant, f64)>,
+    time_stamp: f64,
+    seasons: &[Season],
+) -> (Rating, i32) {
+    match previous {
+        Some(&(ref p, played_at)) => {
+            let rating = match season_starting_between(seasons, played_at, time_stamp) {
+                Some(season) => soft_reset(p.get_rating(), season.reset),
+                None => p.get_rating(),
+            };
+            (rating, p.games_played + 1)
+        }
+        None => (Rating::initial(), 0),
+    }
+}
+
+///
+/// Cursors are the timestamp and id of the last game on a page, which stay
+/// put when newer games are recorded while someone is paging.
+///
+fn encode_cursor(game: &Game) -> String {
+    base64::encode_config(
+        &format!("{}:{}", game.time_stamp, game.id),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+fn decode_cursor(cursor: &str) -> Option<(f64, i32)> {
+    let decoded = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut parts = decoded.splitn(2, ':');
+    let time_stamp = parts.next()?.parse::<f64>().ok()?;
+    let id = parts.next()?.parse::<i32>().ok()?;
+    Some((time_stamp, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: GameRules = GameRules {
+        min_participants: 2,
+        max_participants: 4,
+    };
+
+    fn deck(id: i32, player_id: i32, active: bool) -> Deck {
+        Deck {
+            id,
+            alias: format!("deck {}", id),
+            commander: "Edgar Markov".to_string(),
+            player_id,
+            active,
+        }
+    }
+
+    fn decks() -> HashMap<i32, Deck> {
+        vec![deck(1, 1, true), deck(2, 2, true), deck(3, 3, false)]
+            .into_iter()
+            .map(|deck| (deck.id, deck))
+            .collect()
+    }
+
+    fn requests(deck_ids: &[i32]) -> Vec<ParticipantRequest> {
+        deck_ids
+            .iter()
+            .enumerate()
+            .map(|(index, &deck_id)| ParticipantRequest {
+                deck_id,
+                win: index == 0,
+            })
+            .collect()
+    }
+
+    /// The messages `check_participants` reports, in order
+    fn problems(deck_ids: &[i32], members: Option<&HashSet<i32>>, current: &[i32]) -> Vec<String> {
+        let mut validator = Validator::new();
+        check_participants(
+            &mut validator,
+            &requests(deck_ids),
+            &decks(),
+            members,
+            current,
+            RULES,
+        );
+        match validator.finish() {
+            Ok(()) => vec![],
+            Err(ApiError::Validation(errors)) => errors.into_iter().map(|e| e.message).collect(),
+            Err(_) => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn test_valid_participants_pass() {
+        assert!(problems(&[1, 2], None, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_pod_size_is_checked() {
+        assert_eq!(
+            problems(&[1], None, &[]),
+            vec!["a game needs at least 2 participants"]
+        );
+        assert_eq!(
+            problems(&[1, 2, 4, 5, 6], None, &[])[0],
+            "a game can have at most 4 participants"
+        );
+    }
+
+    #[test]
+    fn test_duplicates_are_reported_before_other_problems() {
+        assert_eq!(
+            problems(&[1, 3, 3, 9, 9], None, &[]),
+            vec![
+                "a game can have at most 4 participants",
+                "deck 3 has been retired",
+                "deck 3 appears more than once",
+                "deck 9 does not exist",
+                "deck 9 appears more than once",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_retired_decks_are_rejected() {
+        assert_eq!(
+            problems(&[1, 3], None, &[]),
+            vec!["deck 3 has been retired"]
+        );
+    }
+
+    #[test]
+    fn test_edits_keep_retired_decks() {
+        assert!(problems(&[1, 3], None, &[1, 3]).is_empty());
+        assert_eq!(
+            problems(&[2, 3], None, &[1, 2]),
+            vec!["deck 3 has been retired"]
+        );
+    }
+
+    #[test]
+    fn test_league_games_need_members() {
+        let members = vec![1, 3].into_iter().collect::<HashSet<_>>();
+        assert_eq!(
+            problems(&[1, 2], Some(&members), &[]),
+            vec!["deck 2 belongs to a player outside the league"]
+        );
+        // Decks already in the game stay when their player leaves
+        assert!(problems(&[1, 2], Some(&members), &[1, 2]).is_empty());
+        assert!(problems(&[1, 2], None, &[]).is_empty());
+    }
+
+    fn game(id: i32, league_id: Option<i32>) -> Game {
+        Game {
+            id,
+            time_stamp: 1546300800.0 + id as f64,
+            league_id,
+        }
+    }
+
+    fn participant(id: i32, game_id: i32, deck_id: i32) -> Participant {
+        Participant {
+            id,
+            game_id,
+            deck_id,
+            win: false,
+            elo: DEFAULT_ELO,
+            deviation: 0.0,
+            volatility: 0.0,
+            games_played: 0,
+            previous_elo: DEFAULT_ELO,
+        }
+    }
+
+    #[test]
+    fn test_every_league_is_replayed_on_its_own() {
+        let games = vec![
+            game(1, None),
+            game(2, Some(7)),
+            game(3, None),
+            game(4, Some(7)),
+            game(5, Some(8)),
+        ];
+        let participants = vec![
+            participant(1, 1, 1),
+            participant(2, 2, 1),
+            participant(3, 3, 2),
+            participant(4, 4, 2),
+            participant(5, 5, 1),
+        ];
+        let groups = group_by_league(games, participants);
+        let ids = |league_id: Option<i32>| {
+            let (ref games, ref participants) = groups[&league_id];
+            (
+                games.iter().map(|g| g.id).collect::<Vec<_>>(),
+                participants.iter().map(|p| p.id).collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(ids(None), (vec![1, 3], vec![1, 3]));
+        assert_eq!(ids(Some(7)), (vec![2, 4], vec![2, 4]));
+        assert_eq!(ids(Some(8)), (vec![5], vec![5]));
+    }
+
+    #[test]
+    fn test_cursor_round_trips() {
+        let game = Game {
+            id: 42,
+            time_stamp: 1546300800.123,
+            league_id: None,
+        };
+        assert_eq!(
+            decode_cursor(&encode_cursor(&game)),
+            Some((1546300800.123, 42))
+        );
+    }
+
+    #[test]
+    fn test_invalid_cursor_is_rejected() {
+        assert_eq!(decode_cursor("not a cursor"), None);
+        assert_eq!(
+            decode_cursor(&base64::encode_config("12", base64::URL_SAFE_NO_PAD)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pool_needs_no_league() {
+        let pool = GameRequestParams {
+            pool: Some(true),
+            ..Default::default()
+        };
+        assert!(pool.validate().is_ok());
+        let both = GameRequestParams {
+            pool: Some(true),
+            league_id: Some(2),
+            ..Default::default()
+        };
+        assert!(both.validate().is_err());
+        let neither = GameRequestParams {
+            pool: Some(false),
+            ..Default::default()
+        };
+        assert!(neither.validate().is_err());
+    }
+
+    #[test]
+    fn test_next_link_keeps_filters() {
+        let params = GameRequestParams {
+            deck_id: Some(3),
+            commander: Some("Edgar Markov".to_string()),
+            from: Some(100),
+            league_id: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            params.next_link(10, "abc"),
+            "/games?limit=10&cursor=abc&deck_id=3&commander=Edgar%20Markov&from=100&league_id=2"
+        );
     }
-    Ok({})
 }
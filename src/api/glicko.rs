@@ -0,0 +1,258 @@
+use api::elo::{outcome_between, GameOutcome, Rankable};
+use api::game::DEFAULT_ELO;
+use api::rating::Rating;
+use config;
+use std::f64::consts::PI;
+
+/// Ratio between the Glicko and Glicko-2 scales
+const GLICKO2_SCALE: f64 = 173.7178;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+pub const DEFAULT_TAU: f64 = 0.5;
+
+lazy_static! {
+    /// Constrains how quickly volatility is allowed to change between games
+    pub static ref TAU: f64 = tau_from_env();
+}
+
+fn tau_from_env() -> f64 {
+    let tau = config::env_or("GLICKO2_TAU", DEFAULT_TAU);
+    if tau <= 0.0 {
+        panic!("GLICKO2_TAU ({}) must be positive", tau);
+    }
+    tau
+}
+
+///
+/// Glicko-2 as described in http://www.glicko.net/glicko/glicko2.pdf
+/// Every game is treated as its own rating period in which each participant
+/// played every other participant, using the same pairings as `Elo`.
+///
+pub trait Glicko2
+where
+    Self: Sized,
+{
+    fn compute_glicko2(entities: &Vec<Self>) -> Vec<Self>;
+}
+
+impl<T> Glicko2 for T
+where
+    T: Rankable,
+{
+    fn compute_glicko2(entities: &Vec<T>) -> Vec<T> {
+        let win_count = entities.iter().filter(|entity| entity.get_win()).count();
+
+        entities
+            .iter()
+            .map(|entity| {
+                // A game without a winner doesn't count, same as with Elo
+                if win_count == 0 {
+                    return entity.clone_with_rating(entity.get_rating());
+                }
+
+                let results = entities
+                    .iter()
+                    .filter(|opponent| opponent.get_unique_id() != entity.get_unique_id())
+                    .filter_map(|opponent| {
+                        outcome_between(entity.get_win(), opponent.get_win())
+                            .map(|outcome| (opponent.get_rating(), outcome))
+                    })
+                    .collect::<Vec<_>>();
+
+                entity.clone_with_rating(glicko2_rating(entity.get_rating(), &results, *TAU))
+            })
+            .collect()
+    }
+}
+
+fn glicko2_rating(rating: Rating, results: &[(Rating, GameOutcome)], tau: f64) -> Rating {
+    let mu = (rating.elo - DEFAULT_ELO) / GLICKO2_SCALE;
+    let phi = rating.deviation / GLICKO2_SCALE;
+    let sigma = rating.volatility;
+
+    if results.is_empty() {
+        return Rating {
+            elo: rating.elo,
+            deviation: (phi.powi(2) + sigma.powi(2)).sqrt() * GLICKO2_SCALE,
+            volatility: sigma,
+        };
+    }
+
+    let mut variance_inverse = 0.0;
+    let mut improvement = 0.0;
+    for &(opponent, outcome) in results {
+        let opponent_mu = (opponent.elo - DEFAULT_ELO) / GLICKO2_SCALE;
+        let opponent_g = g(opponent.deviation / GLICKO2_SCALE);
+        let expected = expected_score(mu, opponent_mu, opponent_g);
+
+        variance_inverse += opponent_g.powi(2) * expected * (1.0 - expected);
+        improvement += opponent_g * (f64::from(outcome) - expected);
+    }
+
+    let variance = 1.0 / variance_inverse;
+    let delta = variance * improvement;
+    let new_sigma = volatility(delta, phi, variance, sigma, tau);
+
+    let phi_star = (phi.powi(2) + new_sigma.powi(2)).sqrt();
+    let new_phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / variance).sqrt();
+    let new_mu = mu + new_phi.powi(2) * improvement;
+
+    Rating {
+        elo: new_mu * GLICKO2_SCALE + DEFAULT_ELO,
+        deviation: new_phi * GLICKO2_SCALE,
+        volatility: new_sigma,
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+fn expected_score(mu: f64, opponent_mu: f64, opponent_g: f64) -> f64 {
+    1.0 / (1.0 + (-opponent_g * (mu - opponent_mu)).exp())
+}
+
+///
+/// Step 5 of the paper, finds the new volatility using the Illinois
+/// variant of regula falsi.
+///
+fn volatility(delta: f64, phi: f64, variance: f64, sigma: f64, tau: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let denominator = 2.0 * (phi.powi(2) + variance + ex).powi(2);
+        ex * (delta.powi(2) - phi.powi(2) - variance - ex) / denominator - (x - a) / tau.powi(2)
+    };
+
+    let mut lower = a;
+    let mut upper = if delta.powi(2) > phi.powi(2) + variance {
+        (delta.powi(2) - phi.powi(2) - variance).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+
+    let mut f_lower = f(lower);
+    let mut f_upper = f(upper);
+    while (upper - lower).abs() > CONVERGENCE_TOLERANCE {
+        let candidate = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+        let f_candidate = f(candidate);
+        if f_candidate * f_upper <= 0.0 {
+            lower = upper;
+            f_lower = f_upper;
+        } else {
+            f_lower /= 2.0;
+        }
+        upper = candidate;
+        f_upper = f_candidate;
+    }
+
+    (lower / 2.0).exp()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use models::participant::NewParticipant;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {} but was {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_glicko2_rating_matches_paper_example() {
+        // The worked example from the paper, shifted from a 1500 to a 1000 baseline
+        let player = Rating {
+            elo: 1000.0,
+            deviation: 200.0,
+            volatility: 0.06,
+        };
+        let results = vec![
+            (
+                Rating {
+                    elo: 900.0,
+                    deviation: 30.0,
+                    volatility: 0.06,
+                },
+                GameOutcome::WIN,
+            ),
+            (
+                Rating {
+                    elo: 1050.0,
+                    deviation: 100.0,
+                    volatility: 0.06,
+                },
+                GameOutcome::LOSE,
+            ),
+            (
+                Rating {
+                    elo: 1200.0,
+                    deviation: 300.0,
+                    volatility: 0.06,
+                },
+                GameOutcome::LOSE,
+            ),
+        ];
+
+        let result = glicko2_rating(player, &results, 0.5);
+
+        assert_close(result.elo, 964.06, 0.01);
+        assert_close(result.deviation, 151.52, 0.01);
+        assert_close(result.volatility, 0.05999, 0.00001);
+    }
+
+    #[test]
+    fn test_glicko2_rating_without_results_only_grows_deviation() {
+        let player = Rating {
+            elo: 1000.0,
+            deviation: 50.0,
+            volatility: 0.06,
+        };
+
+        let result = glicko2_rating(player, &[], 0.5);
+
+        assert_eq!(result.elo, 1000.0);
+        assert_eq!(result.volatility, 0.06);
+        assert!(result.deviation > 50.0);
+    }
+
+    #[test]
+    fn test_compute_glicko2_with_3_participants_and_1_winner() {
+        let test_case = vec![
+            NewParticipant::new(0, 1, true, 1000.0),
+            NewParticipant::new(0, 2, false, 1000.0),
+            NewParticipant::new(0, 3, false, 1000.0),
+        ];
+
+        let result = NewParticipant::compute_glicko2(&test_case);
+
+        assert!(result[0].elo > 1000.0);
+        assert!(result[1].elo < 1000.0);
+        assert_eq!(result[1].elo, result[2].elo);
+        assert!(result[0].deviation < test_case[0].deviation);
+    }
+
+    #[test]
+    fn test_compute_glicko2_with_4_participants_and_0_winners() {
+        let test_case = vec![
+            NewParticipant::new(0, 1, false, 1000.0),
+            NewParticipant::new(0, 2, false, 1000.0),
+            NewParticipant::new(0, 3, false, 1000.0),
+            NewParticipant::new(0, 4, false, 1000.0),
+        ];
+
+        let result = NewParticipant::compute_glicko2(&test_case);
+
+        for (before, after) in test_case.iter().zip(result.iter()) {
+            assert_eq!(before.get_rating(), after.get_rating());
+        }
+    }
+}
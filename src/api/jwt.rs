@@ -0,0 +1,290 @@
+use base64;
+use config;
+use dotenv::dotenv;
+use jsonwebtoken::{self, decode_header, Algorithm, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+/// The id `AUTH_SECRET` is known by, also used for tokens without a `kid`
+pub const DEFAULT_KEY_ID: &str = "default";
+
+lazy_static! {
+    pub static ref KEYSET: KeySet = KeySet::from_env();
+}
+
+///
+/// Every key tokens can be signed with. New tokens are signed with the
+/// current key and carry its id in the `kid` header, tokens signed with any
+/// other key in the set are still accepted until that key is removed.
+///
+pub struct KeySet {
+    current: String,
+    keys: HashMap<String, Key>,
+}
+
+pub struct Key {
+    algorithm: Algorithm,
+    /// `None` for keys that are only kept around to verify older tokens
+    signing: Option<Vec<u8>>,
+    verifying: Vec<u8>,
+}
+
+impl KeySet {
+    ///
+    /// `AUTH_SECRET` is the HS256 key `default`. More keys come from
+    /// `JWT_KEYS`, eg. `2019-01=rs256:private.pem:public.pem,2018-12=hs256:secret`,
+    /// and `JWT_CURRENT_KEY` picks the one new tokens are signed with.
+    ///
+    pub fn from_env() -> KeySet {
+        dotenv().ok();
+        let mut keys = HashMap::new();
+        if let Ok(secret) = env::var("AUTH_SECRET") {
+            if secret.is_empty() {
+                panic!("AUTH_SECRET must not be empty");
+            }
+            keys.insert(
+                DEFAULT_KEY_ID.to_string(),
+                Key::hmac(Algorithm::HS256, &secret),
+            );
+        }
+
+        let specs: String = config::env_or("JWT_KEYS", String::new());
+        for spec in specs.split(',').filter(|spec| !spec.trim().is_empty()) {
+            let (id, key) = parse_key(spec.trim()).unwrap_or_else(|e| panic!("JWT_KEYS: {}", e));
+            keys.insert(id, key);
+        }
+
+        let current: String = config::env_or("JWT_CURRENT_KEY", DEFAULT_KEY_ID.to_string());
+        KeySet::new(current, keys)
+    }
+
+    pub fn new(current: String, keys: HashMap<String, Key>) -> KeySet {
+        match keys.get(&current) {
+            Some(key) if key.signing.is_some() => {}
+            Some(_) => panic!("The current JWT key {} has no private key", current),
+            None if current == DEFAULT_KEY_ID => panic!("AUTH_SECRET must be set"),
+            None => panic!("The current JWT key {} is not in JWT_KEYS", current),
+        }
+        KeySet { current, keys }
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let key = &self.keys[&self.current];
+        let header = Header {
+            kid: Some(self.current.clone()),
+            ..Header::new(key.algorithm)
+        };
+        let signing = key.signing.as_ref().expect("checked in KeySet::new");
+        jsonwebtoken::encode(&header, claims, signing)
+    }
+
+    /// Returns `None` unless the token was signed by a key in the set and is still valid
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Option<T> {
+        let header = decode_header(token).ok()?;
+        let id = header.kid.unwrap_or_else(|| DEFAULT_KEY_ID.to_string());
+        let key = self.keys.get(&id)?;
+
+        // Only the key's own algorithm is allowed, otherwise a public key could be used as an HMAC secret
+        let validation = Validation {
+            algorithms: vec![key.algorithm],
+            ..Validation::default()
+        };
+        jsonwebtoken::decode::<T>(token, &key.verifying, &validation)
+            .ok()
+            .map(|data| data.claims)
+    }
+}
+
+impl Key {
+    pub fn hmac(algorithm: Algorithm, secret: &str) -> Key {
+        Key {
+            algorithm: algorithm,
+            signing: Some(secret.as_bytes().to_vec()),
+            verifying: secret.as_bytes().to_vec(),
+        }
+    }
+
+    ///
+    /// Signs and verifies a throwaway token, which catches a private key that
+    /// can't be used or doesn't belong with the public key. Keys that only
+    /// verify can't be checked this way.
+    ///
+    fn check(&self) -> Result<(), String> {
+        let signing = match self.signing {
+            Some(ref signing) => signing,
+            None => return Ok(()),
+        };
+        let mut claims: HashMap<&str, i64> = HashMap::new();
+        claims.insert("exp", 32503680000); // Year 3000
+        let token = jsonwebtoken::encode(&Header::new(self.algorithm), &claims, signing)
+            .map_err(|_| "the private key can't be used for signing".to_string())?;
+
+        let validation = Validation {
+            algorithms: vec![self.algorithm],
+            ..Validation::default()
+        };
+        jsonwebtoken::decode::<HashMap<String, i64>>(&token, &self.verifying, &validation)
+            .map(|_| ())
+            .map_err(|_| "the public key doesn't verify what the private key signs".to_string())
+    }
+}
+
+///
+/// Parses `<id>=<algorithm>:<key>`. HMAC keys are the secret itself, RSA keys
+/// are the paths of a PKCS#1 private key and public key in PEM format. The
+/// private key can be left out of keys that only verify, eg. `rs256::public.pem`.
+///
+fn parse_key(spec: &str) -> Result<(String, Key), String> {
+    let mut id_and_key = spec.splitn(2, '=');
+    let (id, key) = match (id_and_key.next(), id_and_key.next()) {
+        (Some(id), Some(key)) if !id.is_empty() => (id, key),
+        _ => return Err(format!("{} should look like <id>=<algorithm>:<key>", spec)),
+    };
+
+    let mut parts = key.splitn(2, ':');
+    let algorithm = parts.next().unwrap_or("").to_lowercase();
+    let material = parts.next().unwrap_or("");
+
+    let key = match algorithm.as_ref() {
+        "hs256" => Key::hmac(Algorithm::HS256, material),
+        "hs384" => Key::hmac(Algorithm::HS384, material),
+        "hs512" => Key::hmac(Algorithm::HS512, material),
+        "rs256" => rsa_key(Algorithm::RS256, material)?,
+        "rs384" => rsa_key(Algorithm::RS384, material)?,
+        "rs512" => rsa_key(Algorithm::RS512, material)?,
+        "eddsa" => return Err(format!("{}: EdDSA keys are not supported yet", id)),
+        _ => return Err(format!("{}: unknown algorithm {}", id, algorithm)),
+    };
+
+    if key.verifying.is_empty() {
+        return Err(format!("{}: the key is empty", id));
+    }
+    key.check().map_err(|e| format!("{}: {}", id, e))?;
+    Ok((id.to_string(), key))
+}
+
+fn rsa_key(algorithm: Algorithm, paths: &str) -> Result<Key, String> {
+    let mut paths = paths.splitn(2, ':');
+    let (private, public) = match (paths.next(), paths.next()) {
+        (Some(private), Some(public)) => (private, public),
+        _ => {
+            return Err(format!(
+                "{:?} keys should be <private.pem>:<public.pem>",
+                algorithm
+            ))
+        }
+    };
+
+    Ok(Key {
+        algorithm: algorithm,
+        signing: if private.is_empty() {
+            None
+        } else {
+            Some(read_pem(private)?)
+        },
+        verifying: read_pem(public)?,
+    })
+}
+
+/// The DER bytes inside a PEM file
+fn read_pem(path: &str) -> Result<Vec<u8>, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let body = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+    base64::decode(&body).map_err(|e| format!("{} is not a PEM file: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestClaims {
+        id: i32,
+        exp: i64,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims {
+            id: 1,
+            exp: 32503680000, // Year 3000
+        }
+    }
+
+    fn keyset(current: &str) -> KeySet {
+        let mut keys = HashMap::new();
+        keys.insert("old".to_string(), Key::hmac(Algorithm::HS256, "old-secret"));
+        keys.insert("new".to_string(), Key::hmac(Algorithm::HS512, "new-secret"));
+        KeySet::new(current.to_string(), keys)
+    }
+
+    #[test]
+    fn test_tokens_from_older_keys_still_decode() {
+        let token = keyset("old").encode(&claims()).unwrap();
+
+        assert_eq!(keyset("new").decode::<TestClaims>(&token), Some(claims()));
+        assert_eq!(decode_header(&token).unwrap().kid, Some("old".to_string()));
+    }
+
+    #[test]
+    fn test_tokens_from_removed_keys_are_rejected() {
+        let token = keyset("old").encode(&claims()).unwrap();
+
+        let mut keys = HashMap::new();
+        keys.insert("new".to_string(), Key::hmac(Algorithm::HS512, "new-secret"));
+        let rotated = KeySet::new("new".to_string(), keys);
+
+        assert_eq!(rotated.decode::<TestClaims>(&token), None);
+    }
+
+    #[test]
+    fn test_tokens_without_kid_use_the_default_key() {
+        let token = jsonwebtoken::encode(&Header::default(), &claims(), b"secret").unwrap();
+
+        let mut keys = HashMap::new();
+        keys.insert(
+            DEFAULT_KEY_ID.to_string(),
+            Key::hmac(Algorithm::HS256, "secret"),
+        );
+        let keyset = KeySet::new(DEFAULT_KEY_ID.to_string(), keys);
+
+        assert_eq!(keyset.decode::<TestClaims>(&token), Some(claims()));
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let (id, key) = parse_key("2019-01=hs384:se:cret").unwrap();
+        assert_eq!(id, "2019-01");
+        assert_eq!(key.algorithm, Algorithm::HS384);
+        assert_eq!(key.verifying, b"se:cret".to_vec());
+
+        assert!(parse_key("hs256:secret").is_err());
+        assert!(parse_key("a=eddsa:key.pem").is_err());
+        assert!(parse_key("a=rs256:missing.pem").is_err());
+        assert!(parse_key("a=hs256:").is_err());
+    }
+
+    #[test]
+    fn test_check_key() {
+        assert!(Key::hmac(Algorithm::HS256, "secret").check().is_ok());
+
+        let mismatched = Key {
+            algorithm: Algorithm::RS256,
+            signing: Some(b"not a private key".to_vec()),
+            verifying: b"not a public key".to_vec(),
+        };
+        assert!(mismatched.check().is_err());
+
+        let verify_only = Key {
+            signing: None,
+            ..mismatched
+        };
+        assert!(verify_only.check().is_ok());
+    }
+}
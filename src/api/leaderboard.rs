@@ -0,0 +1,426 @@
+use api::error::ApiError;
+use api::rating::Rating;
+use api::validation::Validator;
+use config;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+lazy_static! {
+    pub static ref LEADERBOARD_DEFAULTS: Criteria = Criteria::from_env();
+}
+
+pub const MAX_SIZE: i32 = 100;
+const SECONDS_PER_DAY: f64 = 86400.0;
+/// How many deviations a conservative estimate stays below the rating
+const CONSERVATIVE_DEVIATIONS: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ranking {
+    /// The rating after the most recent game
+    Current,
+    /// The highest rating ever reached
+    Peak,
+    /// The current rating less two deviations, which favours decks whose
+    /// rating the engine is sure about
+    Conservative,
+}
+
+/// How the decks of a player are combined into a single standing
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregate {
+    /// The average deck score, weighted by how many games each deck played
+    Weighted,
+    /// The score of the player's best deck
+    Best,
+}
+
+/// Who makes it onto a leaderboard and what they are ordered by
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Criteria {
+    pub min_games: i32,
+    /// Only count those who played in this many days, `0` for no limit
+    pub window_days: i32,
+    pub size: i32,
+    pub rank_by: Ranking,
+    /// Only used when ranking players
+    pub aggregate: Aggregate,
+    /// Ranks the members of a league by the games played in it, rather than
+    /// the games played outside of any league
+    pub league_id: Option<i32>,
+}
+
+#[derive(FromForm, Default, Debug)]
+pub struct LeaderboardParams {
+    min_games: Option<i32>,
+    window_days: Option<i32>,
+    limit: Option<i32>,
+    rank_by: Option<String>,
+    aggregate: Option<String>,
+    league_id: Option<i32>,
+}
+
+/// What a leaderboard needs to know about a deck or player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Standing {
+    pub active: bool,
+    pub games: i32,
+    pub last_played: Option<f64>,
+    pub score: f64,
+}
+
+pub struct Ranked<T> {
+    pub rank: usize,
+    pub standing: Standing,
+    pub entry: T,
+}
+
+impl Criteria {
+    fn from_env() -> Criteria {
+        let criteria = Criteria {
+            min_games: config::env_or("LEADERBOARD_MIN_GAMES", 5),
+            window_days: config::env_or("LEADERBOARD_WINDOW_DAYS", 28),
+            size: config::env_or("LEADERBOARD_SIZE", 20),
+            rank_by: config::env_or("LEADERBOARD_RANK_BY", Ranking::Current),
+            aggregate: config::env_or("LEADERBOARD_AGGREGATE", Aggregate::Weighted),
+            league_id: None,
+        };
+        let problems = criteria.problems();
+        if !problems.is_empty() {
+            panic!("{}", problems.join(", "));
+        }
+        criteria
+    }
+
+    /// What is wrong with the settings, held to the same limits as the query parameters
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.min_games < 0 {
+            problems.push(format!(
+                "LEADERBOARD_MIN_GAMES ({}) must not be negative",
+                self.min_games
+            ));
+        }
+        if self.window_days < 0 {
+            problems.push(format!(
+                "LEADERBOARD_WINDOW_DAYS ({}) must not be negative",
+                self.window_days
+            ));
+        }
+        if self.size <= 0 || self.size > MAX_SIZE {
+            problems.push(format!(
+                "LEADERBOARD_SIZE ({}) must be between 1 and {}",
+                self.size, MAX_SIZE
+            ));
+        }
+        problems
+    }
+
+    ///
+    /// Every reason `standing` keeps someone off the leaderboard at `now`,
+    /// empty when they are eligible.
+    ///
+    pub fn exclusions(&self, standing: &Standing, now: f64) -> Vec<String> {
+        let mut reasons = vec![];
+        if !standing.active {
+            reasons.push("is not active".to_string());
+        }
+        if standing.games < self.min_games {
+            reasons.push(format!(
+                "has played {} of the {} games needed",
+                standing.games, self.min_games
+            ));
+        }
+        if self.window_days > 0 {
+            let cutoff = now - self.window_days as f64 * SECONDS_PER_DAY;
+            if standing.last_played.map_or(true, |last| last <= cutoff) {
+                reasons.push(format!(
+                    "has not played in the last {} days",
+                    self.window_days
+                ));
+            }
+        }
+        reasons
+    }
+
+    ///
+    /// The eligible candidates ordered by score, highest first, and numbered
+    /// from 1. Everyone eligible is returned, it's up to the caller to cut
+    /// the list down to `size`.
+    ///
+    pub fn rank<T>(&self, candidates: Vec<(T, Standing)>, now: f64) -> Vec<Ranked<T>> {
+        let mut eligible = candidates
+            .into_iter()
+            .filter(|&(_, ref standing)| self.exclusions(standing, now).is_empty())
+            .collect::<Vec<_>>();
+        eligible.sort_by(|a, b| b.1.score.partial_cmp(&a.1.score).unwrap_or(Ordering::Equal));
+
+        eligible
+            .into_iter()
+            .enumerate()
+            .map(|(index, (entry, standing))| Ranked {
+                rank: index + 1,
+                standing,
+                entry,
+            })
+            .collect()
+    }
+}
+
+impl LeaderboardParams {
+    /// The server defaults with any of the parameters applied on top
+    pub fn criteria(&self) -> Result<Criteria, ApiError> {
+        let defaults = *LEADERBOARD_DEFAULTS;
+        let mut validator = Validator::new();
+        if let Some(min_games) = self.min_games {
+            validator.check(min_games >= 0, "min_games", "must not be negative");
+        }
+        if let Some(window_days) = self.window_days {
+            validator.check(window_days >= 0, "window_days", "must not be negative");
+        }
+        if let Some(limit) = self.limit {
+            validator.check(
+                limit > 0 && limit <= MAX_SIZE,
+                "limit",
+                format!("must be between 1 and {}", MAX_SIZE),
+            );
+        }
+
+        let rank_by = match self.rank_by {
+            Some(ref rank_by) => rank_by.parse::<Ranking>().ok(),
+            None => Some(defaults.rank_by),
+        };
+        validator.check(
+            rank_by.is_some(),
+            "rank_by",
+            "must be one of current, peak or conservative",
+        );
+
+        let aggregate = match self.aggregate {
+            Some(ref aggregate) => aggregate.parse::<Aggregate>().ok(),
+            None => Some(defaults.aggregate),
+        };
+        validator.check(aggregate.is_some(), "aggregate", "must be weighted or best");
+        validator.finish()?;
+
+        Ok(Criteria {
+            min_games: self.min_games.unwrap_or(defaults.min_games),
+            window_days: self.window_days.unwrap_or(defaults.window_days),
+            size: self.limit.unwrap_or(defaults.size),
+            rank_by: rank_by.unwrap_or(defaults.rank_by),
+            aggregate: aggregate.unwrap_or(defaults.aggregate),
+            league_id: self.league_id,
+        })
+    }
+}
+
+impl Ranking {
+    /// Scores a rating history, which is ordered newest first
+    pub fn score(&self, history: &[Rating]) -> Option<f64> {
+        let current = history.first()?;
+        Some(match *self {
+            Ranking::Current => current.elo,
+            Ranking::Peak => history.iter().map(|r| r.elo).fold(current.elo, f64::max),
+            Ranking::Conservative => current.elo - CONSERVATIVE_DEVIATIONS * current.deviation,
+        })
+    }
+}
+
+impl Aggregate {
+    ///
+    /// A player's standing from those of their decks. They count as active
+    /// with any active deck and have played every game their decks have.
+    /// Only decks that have played count towards the score, `unplayed` is
+    /// used when none have.
+    ///
+    pub fn combine(&self, decks: &[Standing], unplayed: f64) -> Standing {
+        let games = decks.iter().map(|deck| deck.games).sum::<i32>();
+        let played = decks.iter().filter(|deck| deck.games > 0);
+        let score = if games == 0 {
+            unplayed
+        } else {
+            match *self {
+                Aggregate::Weighted => {
+                    played
+                        .map(|deck| deck.score * deck.games as f64)
+                        .sum::<f64>()
+                        / games as f64
+                }
+                Aggregate::Best => played.map(|deck| deck.score).fold(f64::MIN, f64::max),
+            }
+        };
+
+        Standing {
+            active: decks.iter().any(|deck| deck.active),
+            games,
+            last_played: decks
+                .iter()
+                .filter_map(|deck| deck.last_played)
+                .fold(None, |latest: Option<f64>, last| {
+                    Some(latest.map_or(last, |latest| latest.max(last)))
+                }),
+            score,
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weighted" => Ok(Aggregate::Weighted),
+            "best" => Ok(Aggregate::Best),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for Ranking {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "current" => Ok(Ranking::Current),
+            "peak" => Ok(Ranking::Peak),
+            "conservative" => Ok(Ranking::Conservative),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1546300800.0;
+
+    fn criteria() -> Criteria {
+        Criteria {
+            min_games: 5,
+            window_days: 28,
+            size: 20,
+            rank_by: Ranking::Current,
+            aggregate: Aggregate::Weighted,
+            league_id: None,
+        }
+    }
+
+    fn standing(games: i32, days_ago: f64, score: f64) -> Standing {
+        Standing {
+            active: true,
+            games,
+            last_played: Some(NOW - days_ago * SECONDS_PER_DAY),
+            score,
+        }
+    }
+
+    fn rating(elo: f64, deviation: f64) -> Rating {
+        Rating {
+            elo,
+            deviation,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn test_settings_are_checked() {
+        assert!(criteria().problems().is_empty());
+        let broken = Criteria {
+            min_games: -1,
+            window_days: -1,
+            size: -1,
+            ..criteria()
+        };
+        assert_eq!(broken.problems().len(), 3);
+        let too_long = Criteria {
+            size: MAX_SIZE + 1,
+            ..criteria()
+        };
+        assert_eq!(
+            too_long.problems(),
+            vec!["LEADERBOARD_SIZE (101) must be between 1 and 100"]
+        );
+    }
+
+    #[test]
+    fn test_explains_every_exclusion() {
+        let retired = Standing {
+            active: false,
+            ..standing(3, 30.0, 1000.0)
+        };
+        assert_eq!(
+            criteria().exclusions(&retired, NOW),
+            vec![
+                "is not active",
+                "has played 3 of the 5 games needed",
+                "has not played in the last 28 days",
+            ]
+        );
+        assert!(criteria()
+            .exclusions(&standing(5, 1.0, 1000.0), NOW)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_window_can_be_disabled() {
+        let criteria = Criteria {
+            window_days: 0,
+            ..criteria()
+        };
+        assert!(criteria
+            .exclusions(&standing(5, 365.0, 1000.0), NOW)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ranks_eligible_by_score() {
+        let ranked = criteria().rank(
+            vec![
+                ("low", standing(9, 1.0, 990.0)),
+                ("new", standing(1, 1.0, 1200.0)),
+                ("high", standing(6, 2.0, 1050.0)),
+            ],
+            NOW,
+        );
+        let order = ranked.iter().map(|r| (r.rank, r.entry)).collect::<Vec<_>>();
+        assert_eq!(order, vec![(1, "high"), (2, "low")]);
+    }
+
+    #[test]
+    fn test_scores_history() {
+        let history = vec![
+            rating(1010.0, 80.0),
+            rating(1060.0, 90.0),
+            rating(1000.0, 350.0),
+        ];
+        assert_eq!(Ranking::Current.score(&history), Some(1010.0));
+        assert_eq!(Ranking::Peak.score(&history), Some(1060.0));
+        assert_eq!(Ranking::Conservative.score(&history), Some(850.0));
+        assert_eq!(Ranking::Current.score(&[]), None);
+    }
+
+    #[test]
+    fn test_combines_decks_into_a_player() {
+        let decks = vec![
+            standing(6, 3.0, 1100.0),
+            standing(2, 1.0, 900.0),
+            Standing {
+                active: false,
+                games: 0,
+                last_played: None,
+                score: 1000.0,
+            },
+        ];
+
+        let weighted = Aggregate::Weighted.combine(&decks, 1000.0);
+        assert_eq!(weighted.score, 1050.0);
+        assert_eq!(weighted.games, 8);
+        assert!(weighted.active);
+        assert_eq!(weighted.last_played, Some(NOW - SECONDS_PER_DAY));
+
+        assert_eq!(Aggregate::Best.combine(&decks, 1000.0).score, 1100.0);
+        assert_eq!(Aggregate::Best.combine(&[], 1000.0).score, 1000.0);
+    }
+}
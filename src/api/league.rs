@@ -0,0 +1,331 @@
+use api::auth::{ApiToken, MemberToken};
+use api::common::current_time;
+use api::error::{ApiError, FieldError};
+use api::validation::Validator;
+use db::DbConn;
+use diesel::prelude::*;
+use models::league::{League, LeagueMember, LeagueRole, NewLeague, NewLeagueMember};
+use models::player::Player;
+use rocket_contrib::json::Json;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+pub struct CreateLeagueRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddMemberRequest {
+    player_id: i32,
+    /// Defaults to `member`
+    role: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMemberRequest {
+    role: String,
+}
+
+#[derive(Serialize)]
+pub struct LeagueResponse {
+    id: i32,
+    name: String,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+pub struct LeagueDetailResponse {
+    #[serde(flatten)]
+    league: LeagueResponse,
+    members: Vec<MemberResponse>,
+}
+
+#[derive(Serialize)]
+pub struct MemberResponse {
+    player_id: i32,
+    alias: String,
+    role: LeagueRole,
+}
+
+impl From<League> for LeagueResponse {
+    fn from(league: League) -> Self {
+        LeagueResponse {
+            id: league.id,
+            name: league.name,
+            created_at: league.created_at,
+        }
+    }
+}
+
+impl MemberResponse {
+    fn new(member: &LeagueMember, player: &Player) -> MemberResponse {
+        MemberResponse {
+            player_id: member.player_id,
+            alias: player.alias.clone(),
+            role: member.role(),
+        }
+    }
+}
+
+#[get("/")]
+pub fn get_leagues(conn: DbConn, _token: ApiToken) -> Result<Json<Vec<LeagueResponse>>, ApiError> {
+    let leagues = League::all(&conn)?;
+    Ok(Json(
+        leagues.into_iter().map(LeagueResponse::from).collect(),
+    ))
+}
+
+#[get("/<id>")]
+pub fn get_league(
+    id: i32,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<LeagueDetailResponse>, ApiError> {
+    let league = League::find_by_id(id, &conn)?;
+    let members = LeagueMember::find_by_league_id(league.id, &conn)?;
+    let players = Player::search(None, None, &conn)?
+        .into_iter()
+        .map(|player| (player.id, player))
+        .collect::<HashMap<_, _>>();
+
+    let members = members
+        .iter()
+        .filter_map(|member| {
+            players
+                .get(&member.player_id)
+                .map(|player| MemberResponse::new(member, player))
+        })
+        .collect();
+
+    Ok(Json(LeagueDetailResponse {
+        league: LeagueResponse::from(league),
+        members,
+    }))
+}
+
+/// Any member can start a league, they become its first admin
+#[post("/", format = "application/json", data = "<req>")]
+pub fn create_league(
+    req: Json<CreateLeagueRequest>,
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<Json<LeagueResponse>, ApiError> {
+    let req = req.into_inner();
+    let mut validator = Validator::new();
+    validator.check(!req.name.trim().is_empty(), "name", "must not be blank");
+    validator.finish()?;
+
+    let league = League::insert(
+        NewLeague {
+            name: req.name.trim().to_string(),
+            created_at: current_time(),
+        },
+        token.0.player_id(),
+        &conn,
+    )?;
+    Ok(Json(LeagueResponse::from(league)))
+}
+
+#[post("/<id>/members", format = "application/json", data = "<req>")]
+pub fn add_member(
+    id: i32,
+    req: Json<AddMemberRequest>,
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<Json<MemberResponse>, ApiError> {
+    let req = req.into_inner();
+    let league = League::find_by_id(id, &conn)?;
+    authorize_league_admin(&token.0, league.id, &conn)?;
+
+    let mut validator = Validator::new();
+    let player = Player::find_by_id(req.player_id, &conn).optional()?;
+    validator.check(
+        player.is_some(),
+        "player_id",
+        format!("player {} does not exist", req.player_id),
+    );
+    let role = match req.role {
+        Some(ref role) => parse_role(&mut validator, role),
+        None => Some(LeagueRole::Member),
+    };
+    validator.finish()?;
+
+    let player = player.ok_or(ApiError::NotFound)?;
+    let role = role.unwrap_or(LeagueRole::Member);
+    let member = NewLeagueMember::insert(
+        NewLeagueMember {
+            league_id: league.id,
+            player_id: player.id,
+            role: role.as_str().to_string(),
+        },
+        &conn,
+    )?;
+    Ok(Json(MemberResponse::new(&member, &player)))
+}
+
+#[put(
+    "/<id>/members/<player_id>",
+    format = "application/json",
+    data = "<req>"
+)]
+pub fn update_member(
+    id: i32,
+    player_id: i32,
+    req: Json<UpdateMemberRequest>,
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<Json<MemberResponse>, ApiError> {
+    let req = req.into_inner();
+    authorize_league_admin(&token.0, id, &conn)?;
+
+    let mut validator = Validator::new();
+    let role = parse_role(&mut validator, &req.role);
+    validator.finish()?;
+    let role = role.unwrap_or(LeagueRole::Member);
+
+    conn.transaction::<_, ApiError, _>(|| {
+        let member = LeagueMember::find(id, player_id, &conn)?;
+        if role != LeagueRole::Admin {
+            keep_an_admin(&member, &conn)?;
+        }
+        let member = LeagueMember::update(
+            LeagueMember {
+                role: role.as_str().to_string(),
+                ..member
+            },
+            &conn,
+        )?;
+        let player = Player::find_by_id(member.player_id, &conn)?;
+        Ok(Json(MemberResponse::new(&member, &player)))
+    })
+}
+
+/// League admins can remove anyone, and every member can leave by themselves
+#[delete("/<id>/members/<player_id>")]
+pub fn remove_member(
+    id: i32,
+    player_id: i32,
+    conn: DbConn,
+    token: MemberToken,
+) -> Result<(), ApiError> {
+    if token.0.player_id() != player_id {
+        authorize_league_admin(&token.0, id, &conn)?;
+    }
+
+    conn.transaction::<_, ApiError, _>(|| {
+        let member = LeagueMember::find(id, player_id, &conn)?;
+        keep_an_admin(&member, &conn)?;
+        member.delete(&conn)?;
+        Ok({})
+    })
+}
+
+///
+/// Global admins manage every league, everyone else has to be one of the
+/// league's admins.
+///
+pub fn is_league_admin(
+    token: &ApiToken,
+    league_id: i32,
+    conn: &SqliteConnection,
+) -> QueryResult<bool> {
+    if token.is_admin() {
+        return Ok(true);
+    }
+    let member = LeagueMember::find(league_id, token.player_id(), conn).optional()?;
+    Ok(member.map_or(false, |member| member.role() == LeagueRole::Admin))
+}
+
+fn authorize_league_admin(
+    token: &ApiToken,
+    league_id: i32,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    if is_league_admin(token, league_id, conn)? {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+/// Only members, and global admins, may record games in a league
+pub fn authorize_league_member(
+    token: &ApiToken,
+    league_id: i32,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    if token.is_admin()
+        || LeagueMember::find(league_id, token.player_id(), conn)
+            .optional()?
+            .is_some()
+    {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+fn parse_role(validator: &mut Validator, role: &str) -> Option<LeagueRole> {
+    let role = role.parse::<LeagueRole>().ok();
+    validator.check(role.is_some(), "role", "must be admin or member");
+    role
+}
+
+///
+/// Refuses to demote or remove `member` when they are the last admin of
+/// their league, which would leave it to the global admins to manage.
+///
+fn keep_an_admin(member: &LeagueMember, conn: &SqliteConnection) -> Result<(), ApiError> {
+    if member.role() != LeagueRole::Admin {
+        return Ok(());
+    }
+    let members = LeagueMember::find_by_league_id(member.league_id, conn)?;
+    if is_last_admin(member, &members) {
+        Err(ApiError::Conflict(vec![FieldError {
+            field: "role".to_string(),
+            message: "a league needs at least one admin".to_string(),
+        }]))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether `member` is an admin and nobody else in `members` is
+fn is_last_admin(member: &LeagueMember, members: &[LeagueMember]) -> bool {
+    member.role() == LeagueRole::Admin
+        && !members
+            .iter()
+            .any(|other| other.id != member.id && other.role() == LeagueRole::Admin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(id: i32, role: LeagueRole) -> LeagueMember {
+        LeagueMember {
+            id,
+            league_id: 1,
+            player_id: id,
+            role: role.as_str().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_the_last_admin_is_kept() {
+        let members = vec![member(1, LeagueRole::Admin), member(2, LeagueRole::Member)];
+        assert!(is_last_admin(&members[0], &members));
+        assert!(!is_last_admin(&members[1], &members));
+    }
+
+    #[test]
+    fn test_admins_can_go_while_another_remains() {
+        let members = vec![
+            member(1, LeagueRole::Admin),
+            member(2, LeagueRole::Admin),
+            member(3, LeagueRole::Member),
+        ];
+        assert!(!is_last_admin(&members[0], &members));
+        assert!(!is_last_admin(&members[1], &members));
+    }
+}
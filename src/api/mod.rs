@@ -1,9 +1,50 @@
+pub mod admin;
+pub mod api_key;
 pub mod auth;
+pub mod commander;
 pub mod deck;
 pub mod error;
 pub mod game;
 pub mod index;
+pub mod league;
 pub mod player;
+pub mod registration;
+pub mod season;
+pub mod throttle;
+pub mod verification;
 
 mod common;
 mod elo;
+mod glicko;
+mod jwt;
+mod leaderboard;
+mod one_time_token;
+mod password;
+mod rating;
+mod trueskill;
+mod validation;
+
+///
+/// Reads the settings that would otherwise only be loaded on first use, so
+/// a misconfigured deployment refuses to start instead of failing requests.
+///
+pub fn load_settings() {
+    ::lazy_static::initialize(&rating::RATING_ENGINE);
+    ::lazy_static::initialize(&glicko::TAU);
+    ::lazy_static::initialize(&elo::K_FACTOR_SCHEDULE);
+    ::lazy_static::initialize(&auth::ACCESS_TOKEN_VALIDITY);
+    ::lazy_static::initialize(&auth::REFRESH_TOKEN_VALIDITY);
+    ::lazy_static::initialize(&auth::PASSWORD_RESET_VALIDITY);
+    ::lazy_static::initialize(&auth::PASSWORD_RESET_URL);
+    ::lazy_static::initialize(&password::PASSWORD_POLICY);
+    ::lazy_static::initialize(&jwt::KEYSET);
+    ::lazy_static::initialize(&registration::REGISTRATION_MODE);
+    ::lazy_static::initialize(&registration::INVITE_VALIDITY);
+    ::lazy_static::initialize(&verification::REQUIRE_EMAIL_VERIFICATION);
+    ::lazy_static::initialize(&verification::EMAIL_VERIFICATION_VALIDITY);
+    ::lazy_static::initialize(&verification::EMAIL_VERIFICATION_URL);
+    ::lazy_static::initialize(&throttle::CLIENT_IP_HEADER);
+    ::lazy_static::initialize(&validation::GAME_RULES);
+    ::lazy_static::initialize(&leaderboard::LEADERBOARD_DEFAULTS);
+    ::lazy_static::initialize(&commander::COMMANDER_TREND_WEEKS);
+}
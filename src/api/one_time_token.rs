@@ -0,0 +1,75 @@
+use api::auth::{SELECTOR_LENGTH, VERIFIER_LENGTH};
+use api::common::{current_time, digest, digest_matches, random_string};
+use api::error::{ApiError, FieldError};
+use bcrypt::verify;
+use diesel::prelude::*;
+use models::one_time_token::{NewOneTimeToken, OneTimeToken, Purpose};
+
+///
+/// Creates a token for `purpose` and returns the secret to hand to the
+/// player, in the form `selector.verifier`. Any older token the player had
+/// for the same purpose stops working.
+///
+pub fn issue(
+    player_id: i32,
+    purpose: Purpose,
+    validity: i64,
+    conn: &SqliteConnection,
+) -> Result<String, ApiError> {
+    let now = current_time();
+    let selector = random_string(SELECTOR_LENGTH);
+    let verifier = random_string(VERIFIER_LENGTH);
+
+    OneTimeToken::delete_expired(now, conn)?;
+    OneTimeToken::delete_by_player(player_id, purpose, conn)?;
+    OneTimeToken::insert(
+        NewOneTimeToken {
+            player_id: player_id,
+            purpose: purpose.as_str().to_string(),
+            selector: selector.clone(),
+            verifier: digest(&verifier),
+            expires_at: now + validity,
+        },
+        conn,
+    )?;
+
+    Ok(format!("{}.{}", selector, verifier))
+}
+
+///
+/// Checks a secret produced by `issue` and uses it up, returning the id of
+/// the player it was issued to. Unknown, expired and already used tokens are
+/// all reported the same way.
+///
+pub fn redeem(secret: &str, purpose: Purpose, conn: &SqliteConnection) -> Result<i32, ApiError> {
+    let mut parts = secret.splitn(2, '.');
+    let (selector, verifier) = match (parts.next(), parts.next()) {
+        (Some(selector), Some(verifier)) => (selector, verifier),
+        _ => return Err(invalid_token()),
+    };
+
+    let token = match OneTimeToken::find_by_selector(selector, purpose, conn).optional()? {
+        Some(token) => token,
+        None => return Err(invalid_token()),
+    };
+
+    // Tokens issued before verifiers were stored as digests still have a bcrypt hash
+    let matches = if token.verifier.starts_with("$2") {
+        verify(verifier, &token.verifier)?
+    } else {
+        digest_matches(verifier, &token.verifier)
+    };
+    if token.expires_at <= current_time() || !matches {
+        return Err(invalid_token());
+    }
+
+    OneTimeToken::delete(token.id, conn)?;
+    Ok(token.player_id)
+}
+
+fn invalid_token() -> ApiError {
+    ApiError::Validation(vec![FieldError {
+        field: "token".to_string(),
+        message: "is invalid or has expired".to_string(),
+    }])
+}
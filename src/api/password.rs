@@ -0,0 +1,305 @@
+use api::error::ApiError;
+use api::validation::Validator;
+use argon2::{self, Config, ThreadMode, Variant, Version};
+use bcrypt;
+use config;
+use rand::{thread_rng, Rng};
+use std::str::FromStr;
+
+const ARGON2ID_PREFIX: &str = "$argon2id$";
+const MIN_BCRYPT_COST: u32 = 4;
+const MAX_BCRYPT_COST: u32 = 31;
+/// Argon2 needs at least this much memory, in KiB, for every lane
+const ARGON2_MEMORY_PER_LANE: u32 = 8;
+const MAX_ARGON2_PARALLELISM: u32 = 0x00FF_FFFF;
+
+lazy_static! {
+    pub static ref PASSWORD_POLICY: PasswordPolicy = PasswordPolicy::from_env();
+}
+
+///
+/// How passwords are hashed and what they have to look like. Hashes made
+/// with other parameters still verify and are replaced on the next login,
+/// see `needs_rehash`.
+///
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct PasswordPolicy {
+    pub algorithm: Algorithm,
+    pub bcrypt_cost: u32,
+    /// In KiB
+    pub argon2_memory: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub min_length: usize,
+    /// Out of lowercase, uppercase, digits and everything else
+    pub min_character_classes: usize,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Algorithm {
+    Bcrypt,
+    Argon2id,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> PasswordPolicy {
+        let policy = PasswordPolicy {
+            algorithm: config::env_or("PASSWORD_HASH", Algorithm::Bcrypt),
+            bcrypt_cost: config::env_or("BCRYPT_COST", 10),
+            argon2_memory: config::env_or("ARGON2_MEMORY", 65536),
+            argon2_iterations: config::env_or("ARGON2_ITERATIONS", 3),
+            argon2_parallelism: config::env_or("ARGON2_PARALLELISM", 1),
+            min_length: config::env_or("PASSWORD_MIN_LENGTH", 8),
+            min_character_classes: config::env_or("PASSWORD_MIN_CHARACTER_CLASSES", 2),
+        };
+        let problems = policy.problems();
+        if !problems.is_empty() {
+            panic!("{}", problems.join(", "));
+        }
+        policy
+    }
+
+    ///
+    /// What is wrong with the settings. Both algorithms are checked so that
+    /// switching `PASSWORD_HASH` can't bring up a setting that fails to hash.
+    ///
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.bcrypt_cost < MIN_BCRYPT_COST || self.bcrypt_cost > MAX_BCRYPT_COST {
+            problems.push(format!(
+                "BCRYPT_COST ({}) must be between {} and {}",
+                self.bcrypt_cost, MIN_BCRYPT_COST, MAX_BCRYPT_COST
+            ));
+        }
+        if self.argon2_iterations == 0 {
+            problems.push("ARGON2_ITERATIONS must be at least 1".to_string());
+        }
+        if self.argon2_parallelism == 0 || self.argon2_parallelism > MAX_ARGON2_PARALLELISM {
+            problems.push(format!(
+                "ARGON2_PARALLELISM ({}) must be between 1 and {}",
+                self.argon2_parallelism, MAX_ARGON2_PARALLELISM
+            ));
+        } else if self.argon2_memory < ARGON2_MEMORY_PER_LANE * self.argon2_parallelism {
+            problems.push(format!(
+                "ARGON2_MEMORY ({}) must be at least {} KiB for every lane",
+                self.argon2_memory, ARGON2_MEMORY_PER_LANE
+            ));
+        }
+        if self.min_character_classes > 4 {
+            problems.push(format!(
+                "PASSWORD_MIN_CHARACTER_CLASSES ({}) must be at most 4",
+                self.min_character_classes
+            ));
+        }
+        problems
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, ApiError> {
+        match self.algorithm {
+            Algorithm::Bcrypt => Ok(bcrypt::hash(password, self.bcrypt_cost)?),
+            Algorithm::Argon2id => {
+                let salt = thread_rng().gen::<[u8; 16]>();
+                Ok(argon2::hash_encoded(
+                    password.as_bytes(),
+                    &salt,
+                    &self.argon2_config(),
+                )?)
+            }
+        }
+    }
+
+    /// Works for hashes made with any algorithm or parameters
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool, ApiError> {
+        if hash.starts_with(ARGON2ID_PREFIX) {
+            Ok(argon2::verify_encoded(hash, password.as_bytes())?)
+        } else {
+            Ok(bcrypt::verify(password, hash)?)
+        }
+    }
+
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match self.algorithm {
+            Algorithm::Bcrypt => bcrypt_cost(hash) != Some(self.bcrypt_cost),
+            Algorithm::Argon2id => {
+                let parameters = format!(
+                    "{}v={}$m={},t={},p={}$",
+                    ARGON2ID_PREFIX,
+                    Version::Version13.as_u32(),
+                    self.argon2_memory,
+                    self.argon2_iterations,
+                    self.argon2_parallelism
+                );
+                !hash.starts_with(&parameters)
+            }
+        }
+    }
+
+    ///
+    /// Adds an error for `field` if the password is too weak. `personal` are
+    /// things like the alias and email that the password must not contain.
+    ///
+    pub fn check(&self, validator: &mut Validator, field: &str, password: &str, personal: &[&str]) {
+        if password.chars().count() < self.min_length {
+            validator.error(
+                field,
+                format!("must be at least {} characters", self.min_length),
+            );
+        }
+
+        if character_classes(password) < self.min_character_classes {
+            validator.error(
+                field,
+                format!(
+                    "must mix at least {} of lowercase letters, uppercase letters, digits and symbols",
+                    self.min_character_classes
+                ),
+            );
+        }
+
+        let lowercase = password.to_lowercase();
+        let contains_personal = personal
+            .iter()
+            .filter(|value| value.len() >= 3)
+            .any(|value| lowercase.contains(&value.to_lowercase()));
+        validator.check(
+            !contains_personal,
+            field,
+            "can't contain your alias or email",
+        );
+    }
+
+    fn argon2_config(&self) -> Config {
+        Config {
+            variant: Variant::Argon2id,
+            version: Version::Version13,
+            mem_cost: self.argon2_memory,
+            time_cost: self.argon2_iterations,
+            lanes: self.argon2_parallelism,
+            thread_mode: ThreadMode::Sequential,
+            ..Config::default()
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "bcrypt" => Ok(Algorithm::Bcrypt),
+            "argon2id" | "argon2" => Ok(Algorithm::Argon2id),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Bcrypt hashes look like `$2y$10$<salt and hash>`
+fn bcrypt_cost(hash: &str) -> Option<u32> {
+    let mut parts = hash.split('$');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(""), Some(version), Some(cost)) if version.starts_with('2') => cost.parse().ok(),
+        _ => None,
+    }
+}
+
+fn character_classes(password: &str) -> usize {
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_numeric()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    classes.iter().filter(|&&present| present).count()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            algorithm: Algorithm::Bcrypt,
+            bcrypt_cost: 10,
+            argon2_memory: 65536,
+            argon2_iterations: 3,
+            argon2_parallelism: 1,
+            min_length: 8,
+            min_character_classes: 2,
+        }
+    }
+
+    fn errors(password: &str) -> usize {
+        let mut validator = Validator::new();
+        policy().check(
+            &mut validator,
+            "password",
+            password,
+            &["sample", "sample@example.com"],
+        );
+        match validator.finish() {
+            Ok(()) => 0,
+            Err(ApiError::Validation(errors)) => errors.len(),
+            Err(_) => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn test_bcrypt_cost() {
+        assert_eq!(
+            bcrypt_cost("$2y$08$kPpS2tNmBOoyxNX8KrZfyedDAFfn4p3n5H9nU5tKDqLgHYGGWBJ3G"),
+            Some(8)
+        );
+        assert_eq!(
+            bcrypt_cost("$argon2id$v=19$m=65536,t=3,p=1$c2FsdA$aGFzaA"),
+            None
+        );
+        assert_eq!(bcrypt_cost("plaintext"), None);
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let bcrypt_policy = policy();
+        let argon2_policy = PasswordPolicy {
+            algorithm: Algorithm::Argon2id,
+            ..policy()
+        };
+        let bcrypt_8 = "$2y$08$kPpS2tNmBOoyxNX8KrZfyedDAFfn4p3n5H9nU5tKDqLgHYGGWBJ3G";
+        let bcrypt_10 = "$2y$10$kPpS2tNmBOoyxNX8KrZfyedDAFfn4p3n5H9nU5tKDqLgHYGGWBJ3G";
+        let argon2 = "$argon2id$v=19$m=65536,t=3,p=1$c2FsdA$aGFzaA";
+
+        assert!(bcrypt_policy.needs_rehash(bcrypt_8));
+        assert!(!bcrypt_policy.needs_rehash(bcrypt_10));
+        assert!(bcrypt_policy.needs_rehash(argon2));
+        assert!(argon2_policy.needs_rehash(bcrypt_10));
+        assert!(!argon2_policy.needs_rehash(argon2));
+    }
+
+    #[test]
+    fn test_policy_problems() {
+        assert!(policy().problems().is_empty());
+        let broken = PasswordPolicy {
+            bcrypt_cost: 3,
+            argon2_iterations: 0,
+            argon2_parallelism: 0,
+            min_character_classes: 5,
+            ..policy()
+        };
+        assert_eq!(broken.problems().len(), 4);
+        let starved = PasswordPolicy {
+            bcrypt_cost: 32,
+            argon2_memory: 15,
+            argon2_parallelism: 2,
+            ..policy()
+        };
+        assert_eq!(starved.problems().len(), 2);
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert_eq!(errors("correct horse 42"), 0);
+        assert_eq!(errors("Sh0rt"), 1);
+        assert_eq!(errors("alllowercase"), 1);
+        assert_eq!(errors("mysample99"), 1);
+    }
+}
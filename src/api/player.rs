@@ -1,17 +1,32 @@
-use api::auth::ApiToken;
-use api::deck::DeckResponse;
+use api::auth::{ApiToken, MemberToken};
+use api::common::{current_time, ListParams, PaginatedResponse, Sortable};
+use api::deck::{deck_standings, DeckResponse};
 use api::error::ApiError;
-use bcrypt::hash;
+use api::game::DEFAULT_ELO;
+use api::leaderboard::LeaderboardParams;
+use api::password::PASSWORD_POLICY;
+use api::registration::{redeem_invite, REGISTRATION_MODE};
+use api::validation::{is_email, Validator};
+use api::verification::send_verification;
 use db::DbConn;
+use diesel::Connection;
+use mailer::Mailer;
 use models::deck::Deck;
-use models::player::{NewPlayer, Player};
+use models::league::LeagueMember;
+use models::player::{AccountStatus, NewPlayer, Player, Role};
+use models::token::Token;
+use rocket::request::Form;
+use rocket::State;
 use rocket_contrib::json::Json;
+use std::collections::HashMap;
 
 #[derive(Deserialize)]
 pub struct CreatePlayerRequest {
     alias: String,
     email: String,
     password: String,
+    /// Only needed when `REGISTRATION_MODE` is `invite`
+    invite_code: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -19,69 +34,241 @@ pub struct UpdatePlayerRequest {
     id: i32,
     alias: Option<String>,
     email: Option<String>,
+    role: Option<Role>,
 }
 
 #[derive(Serialize)]
 pub struct PlayerResponse {
     id: i32,
     alias: String,
+    role: Role,
+    verified: bool,
+    status: AccountStatus,
 }
 
 #[derive(Serialize)]
 pub struct PlayerDetailResponse {
     id: i32,
     alias: String,
+    role: Role,
+    games: i32,
+    wins: i32,
+    win_percentage: f64,
+    /// The rating of the player's best deck
+    elo: f64,
     decks: Vec<DeckResponse>,
 }
 
+#[derive(Serialize)]
+pub struct PlayerLeaderboardEntry {
+    rank: usize,
+    /// The player's decks combined as set by `aggregate`
+    score: f64,
+    #[serde(flatten)]
+    player: PlayerDetailResponse,
+}
+
+impl PlayerDetailResponse {
+    fn new(player: Player, decks: Vec<DeckResponse>) -> PlayerDetailResponse {
+        let games = decks.iter().map(|deck| deck.games).sum::<i32>();
+        let wins = decks.iter().map(|deck| deck.wins).sum::<i32>();
+        let win_percentage = if games > 0 {
+            wins as f64 / games as f64
+        } else {
+            0.0
+        };
+        let elo = if decks.is_empty() {
+            DEFAULT_ELO
+        } else {
+            decks.iter().map(|deck| deck.elo).fold(f64::MIN, f64::max)
+        };
+
+        PlayerDetailResponse {
+            id: player.id,
+            role: player.role(),
+            alias: player.alias,
+            games,
+            wins,
+            win_percentage,
+            elo,
+            decks,
+        }
+    }
+}
+
+impl Sortable for PlayerDetailResponse {
+    fn elo(&self) -> f64 {
+        self.elo
+    }
+
+    fn games(&self) -> i32 {
+        self.games
+    }
+
+    fn win_rate(&self) -> f64 {
+        self.win_percentage
+    }
+
+    fn alias(&self) -> &str {
+        &self.alias
+    }
+}
+
 impl Player {
-    fn into_player_response(self) -> PlayerResponse {
+    pub fn into_player_response(self) -> PlayerResponse {
         PlayerResponse {
             id: self.id,
+            role: self.role(),
+            verified: self.verified,
+            status: self.status(),
             alias: self.alias,
         }
     }
 }
 
 impl CreatePlayerRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut validator = Validator::new();
+        validator.check(
+            is_email(&self.email),
+            "email",
+            "is not a valid email address",
+        );
+        PASSWORD_POLICY.check(
+            &mut validator,
+            "password",
+            &self.password,
+            &[self.alias.as_str(), self.email.as_str()],
+        );
+        validator.finish()
+    }
+
     fn into_new_player(self) -> Result<NewPlayer, ApiError> {
         Ok(NewPlayer {
+            password: PASSWORD_POLICY.hash(&self.password)?,
             alias: self.alias,
             email: self.email,
-            password: hash(&self.password, /*cost*/ 8)?,
+            status: REGISTRATION_MODE.initial_status().as_str().to_string(),
         })
     }
 }
 
+impl UpdatePlayerRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut validator = Validator::new();
+        if let Some(ref email) = self.email {
+            validator.check(is_email(email), "email", "is not a valid email address");
+        }
+        validator.finish()
+    }
+}
+
 #[post("/", format = "application/json", data = "<json>")]
 pub fn create_player(
     json: Json<CreatePlayerRequest>,
     conn: DbConn,
+    mailer: State<Box<dyn Mailer>>,
 ) -> Result<Json<PlayerResponse>, ApiError> {
     let req = json.into_inner();
-    let new_player = NewPlayer::insert(req.into_new_player()?, &conn);
+    req.validate()?;
 
-    Ok(Json(new_player?.into_player_response()))
+    // Without the email the account could never be verified, so it isn't created either
+    conn.transaction::<_, ApiError, _>(|| {
+        let invite = REGISTRATION_MODE
+            .required_invite(req.invite_code.as_ref().map(String::as_str), &conn)?;
+        let player = NewPlayer::insert(req.into_new_player()?, &conn)?;
+        if let Some(invite) = invite {
+            redeem_invite(&invite, player.id, &conn)?;
+        }
+
+        send_verification(&player, &**mailer, &conn)?;
+        Ok(Json(player.into_player_response()))
+    })
 }
 
-#[get("/")]
+#[get("/?<params..>")]
 pub fn get_players(
+    params: Form<ListParams>,
     conn: DbConn,
     _token: ApiToken,
-) -> Result<Json<Vec<PlayerDetailResponse>>, ApiError> {
-    let players = Player::all(&conn)?;
-    let decks_by_players = Deck::find_by_players(players, &conn)?;
-
-    let mut response = vec![];
-    for (player, decks) in decks_by_players {
-        let deck_response = DeckResponse::into_deck_response(decks, &conn)?;
-        response.push(PlayerDetailResponse {
-            id: player.id,
-            alias: player.alias,
-            decks: deck_response,
-        });
+) -> Result<Json<PaginatedResponse<PlayerDetailResponse>>, ApiError> {
+    let options = params.validate()?;
+    let players = Player::search(
+        options.search.as_ref().map(String::as_str),
+        options.active,
+        &conn,
+    )?;
+
+    // Stats for every deck are computed at once rather than player by player
+    let decks = Deck::find_by_players(&players, &conn)?;
+    let mut decks_by_player = HashMap::new();
+    for deck in DeckResponse::into_deck_response(decks, &conn)? {
+        decks_by_player
+            .entry(deck.player_id)
+            .or_insert_with(Vec::new)
+            .push(deck);
     }
 
+    let response = players
+        .into_iter()
+        .map(|player| {
+            let decks = decks_by_player.remove(&player.id).unwrap_or_default();
+            PlayerDetailResponse::new(player, decks)
+        })
+        .collect();
+
+    Ok(Json(options.paginate(response, "/players")))
+}
+
+#[get("/leaderboard?<params..>")]
+pub fn get_player_leaderboard(
+    params: Form<LeaderboardParams>,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<Vec<PlayerLeaderboardEntry>>, ApiError> {
+    let criteria = params.criteria()?;
+
+    let mut decks_by_player = HashMap::new();
+    for (deck, standing) in deck_standings(&criteria, &conn)? {
+        decks_by_player
+            .entry(deck.player_id)
+            .or_insert_with(Vec::new)
+            .push((deck, standing));
+    }
+
+    let members = match criteria.league_id {
+        Some(league_id) => Some(LeagueMember::player_ids(league_id, &conn)?),
+        None => None,
+    };
+
+    let candidates = Player::search(None, None, &conn)?
+        .into_iter()
+        .filter(|player| {
+            members
+                .as_ref()
+                .map_or(true, |members| members.contains(&player.id))
+        })
+        .map(|player| {
+            let (decks, standings): (Vec<_>, Vec<_>) = decks_by_player
+                .remove(&player.id)
+                .unwrap_or_default()
+                .into_iter()
+                .unzip();
+            let standing = criteria.aggregate.combine(&standings, DEFAULT_ELO);
+            (PlayerDetailResponse::new(player, decks), standing)
+        })
+        .collect();
+
+    let response = criteria
+        .rank(candidates, current_time() as f64)
+        .into_iter()
+        .take(criteria.size as usize)
+        .map(|ranked| PlayerLeaderboardEntry {
+            rank: ranked.rank,
+            score: ranked.standing.score,
+            player: ranked.entry,
+        })
+        .collect();
     Ok(Json(response))
 }
 
@@ -94,11 +281,8 @@ pub fn get_player(
     let player = Player::find_by_id(id, &conn)?;
     let decks = Deck::find_by_player(&player, &conn)?;
 
-    let response = PlayerDetailResponse {
-        id: player.id,
-        alias: player.alias,
-        decks: DeckResponse::into_deck_response(decks, &conn)?,
-    };
+    let response =
+        PlayerDetailResponse::new(player, DeckResponse::into_deck_response(decks, &conn)?);
 
     Ok(Json(response))
 }
@@ -107,25 +291,46 @@ pub fn get_player(
 pub fn update_player(
     req: Json<UpdatePlayerRequest>,
     conn: DbConn,
-    _token: ApiToken,
+    token: MemberToken,
+    mailer: State<Box<dyn Mailer>>,
 ) -> Result<Json<PlayerResponse>, ApiError> {
     let update_request = req.into_inner();
+    update_request.validate()?;
+    let token = token.0;
 
     let old_player = Player::find_by_id(update_request.id, &conn)?;
-    let new_player = Player::update(
-        Player {
-            id: old_player.id,
-            alias: update_request.alias.unwrap_or(old_player.alias),
-            email: update_request.email.unwrap_or(old_player.email),
-            password: old_player.password,
-        },
-        &conn,
-    )?;
+    token.authorize(old_player.id)?;
+    if update_request.role.is_some() && !token.is_admin() {
+        return Err(ApiError::Forbidden);
+    }
 
-    let response = PlayerResponse {
-        id: new_player.id,
-        alias: new_player.alias,
-    };
+    let role = update_request.role.unwrap_or(old_player.role());
+    conn.transaction::<_, ApiError, _>(|| {
+        // Tokens carry the role, so they have to be reissued when it changes
+        if role != old_player.role() {
+            Token::delete_by_player(old_player.id, &conn)?;
+        }
 
-    Ok(Json(response))
+        // A new email has to be verified again
+        let email = update_request.email.unwrap_or(old_player.email.clone());
+        let email_changed = email != old_player.email;
+
+        let new_player = Player::update(
+            Player {
+                id: old_player.id,
+                alias: update_request.alias.unwrap_or(old_player.alias),
+                email: email,
+                password: old_player.password,
+                role: role.as_str().to_string(),
+                status: old_player.status,
+                verified: old_player.verified && !email_changed,
+            },
+            &conn,
+        )?;
+        if email_changed {
+            send_verification(&new_player, &**mailer, &conn)?;
+        }
+
+        Ok(Json(new_player.into_player_response()))
+    })
 }
@@ -0,0 +1,65 @@
+use api::elo::{Elo, Rankable};
+use api::game::DEFAULT_ELO;
+use api::glicko::Glicko2;
+use api::trueskill::TrueSkill;
+use config;
+use std::str::FromStr;
+
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+
+lazy_static! {
+    pub static ref RATING_ENGINE: RatingEngine = config::env_or("RATING_ENGINE", RatingEngine::Elo);
+}
+
+///
+/// Everything a rating engine knows about a deck at a point in time.
+/// Elo only uses `elo`, Glicko-2 uses all three and TrueSkill stores its
+/// mean in `elo` and its standard deviation in `deviation`.
+///
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Rating {
+    pub elo: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RatingEngine {
+    Elo,
+    Glicko2,
+    TrueSkill,
+}
+
+impl Rating {
+    pub fn initial() -> Rating {
+        Rating {
+            elo: DEFAULT_ELO,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+impl RatingEngine {
+    pub fn compute<T: Rankable>(&self, entities: &Vec<T>) -> Vec<T> {
+        match *self {
+            RatingEngine::Elo => T::compute_elo(entities),
+            RatingEngine::Glicko2 => T::compute_glicko2(entities),
+            RatingEngine::TrueSkill => T::compute_trueskill(entities),
+        }
+    }
+}
+
+impl FromStr for RatingEngine {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "elo" => Ok(RatingEngine::Elo),
+            "glicko2" | "glicko-2" => Ok(RatingEngine::Glicko2),
+            "trueskill" => Ok(RatingEngine::TrueSkill),
+            _ => Err(()),
+        }
+    }
+}
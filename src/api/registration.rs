@@ -0,0 +1,98 @@
+use api::common::current_time;
+use api::error::{ApiError, FieldError};
+use config;
+use diesel::prelude::*;
+use models::invite::Invite;
+use models::player::AccountStatus;
+use std::str::FromStr;
+
+lazy_static! {
+    pub static ref REGISTRATION_MODE: RegistrationMode =
+        config::env_or("REGISTRATION_MODE", RegistrationMode::Open);
+    /// How long an invite code can be used for, in seconds
+    pub static ref INVITE_VALIDITY: i64 = config::seconds_or("INVITE_VALIDITY", 604800);
+}
+
+/// Who is allowed to create an account with `POST /players`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RegistrationMode {
+    Open,
+    /// Players need an invite code from an admin
+    Invite,
+    /// Anyone can register but an admin has to approve them before they can log in
+    Approval,
+}
+
+impl RegistrationMode {
+    pub fn initial_status(&self) -> AccountStatus {
+        match *self {
+            RegistrationMode::Approval => AccountStatus::Pending,
+            RegistrationMode::Open | RegistrationMode::Invite => AccountStatus::Active,
+        }
+    }
+
+    ///
+    /// Finds the invite a new player has to use up, if this mode needs one.
+    /// Using it is left to `redeem_invite` once the player exists.
+    ///
+    pub fn required_invite(
+        &self,
+        code: Option<&str>,
+        conn: &SqliteConnection,
+    ) -> Result<Option<Invite>, ApiError> {
+        if *self != RegistrationMode::Invite {
+            return Ok(None);
+        }
+
+        let code = code.ok_or_else(|| invite_error("is required"))?;
+        match Invite::find_by_code(code, conn).optional()? {
+            Some(ref invite) if invite.used_by.is_some() => {
+                Err(invite_error("has already been used"))
+            }
+            Some(ref invite) if invite.expires_at <= current_time() => {
+                Err(invite_error("has expired"))
+            }
+            Some(invite) => Ok(Some(invite)),
+            None => Err(invite_error("is invalid")),
+        }
+    }
+}
+
+///
+/// Uses up the invite for `player_id`. The invite is checked again as it is
+/// used, so when another sign up took it since `required_invite` found it
+/// this fails and the caller's transaction is rolled back.
+///
+pub fn redeem_invite(
+    invite: &Invite,
+    player_id: i32,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    if Invite::redeem(invite.id, player_id, current_time(), conn)? == 0 {
+        return Err(ApiError::Conflict(vec![FieldError {
+            field: "invite_code".to_string(),
+            message: "has already been used or has expired".to_string(),
+        }]));
+    }
+    Ok(())
+}
+
+impl FromStr for RegistrationMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "open" => Ok(RegistrationMode::Open),
+            "invite" => Ok(RegistrationMode::Invite),
+            "approval" => Ok(RegistrationMode::Approval),
+            _ => Err(()),
+        }
+    }
+}
+
+fn invite_error(message: &str) -> ApiError {
+    ApiError::Validation(vec![FieldError {
+        field: "invite_code".to_string(),
+        message: message.to_string(),
+    }])
+}
@@ -0,0 +1,257 @@
+use api::auth::ApiToken;
+use api::error::ApiError;
+use api::rating::Rating;
+use db::DbConn;
+use diesel::prelude::*;
+use models::deck::Deck;
+use models::participant::Participant;
+use models::season::{NewSeasonStanding, Season, SeasonStanding};
+use rocket_contrib::json::Json;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+pub struct SeasonResponse {
+    id: i32,
+    name: String,
+    starts_at: i64,
+    ends_at: i64,
+    reset: f64,
+    closed: bool,
+}
+
+#[derive(Serialize)]
+pub struct SeasonDetailResponse {
+    #[serde(flatten)]
+    season: SeasonResponse,
+    /// Final once the season is closed, otherwise as things stand
+    standings: Vec<StandingResponse>,
+}
+
+#[derive(Serialize)]
+pub struct StandingResponse {
+    rank: i32,
+    deck_id: i32,
+    alias: String,
+    commander: String,
+    elo: f64,
+    games: i32,
+    wins: i32,
+}
+
+impl From<Season> for SeasonResponse {
+    fn from(season: Season) -> Self {
+        SeasonResponse {
+            id: season.id,
+            name: season.name,
+            starts_at: season.starts_at,
+            ends_at: season.ends_at,
+            reset: season.reset,
+            closed: season.closed,
+        }
+    }
+}
+
+#[get("/")]
+pub fn get_seasons(conn: DbConn, _token: ApiToken) -> Result<Json<Vec<SeasonResponse>>, ApiError> {
+    let seasons = Season::all(&conn)?;
+    Ok(Json(
+        seasons.into_iter().map(SeasonResponse::from).collect(),
+    ))
+}
+
+#[get("/<id>")]
+pub fn get_season(
+    id: i32,
+    conn: DbConn,
+    _token: ApiToken,
+) -> Result<Json<SeasonDetailResponse>, ApiError> {
+    let season = Season::find_by_id(id, &conn)?;
+    let standings = if season.closed {
+        SeasonStanding::find_by_season(&season, &conn)?
+            .into_iter()
+            .map(|s| NewSeasonStanding {
+                season_id: s.season_id,
+                deck_id: s.deck_id,
+                rank: s.rank,
+                elo: s.elo,
+                games: s.games,
+                wins: s.wins,
+            })
+            .collect()
+    } else {
+        current_standings(&season, &conn)?
+    };
+
+    let deck_ids = standings.iter().map(|s| s.deck_id).collect::<Vec<_>>();
+    let decks = Deck::find_by_ids(&deck_ids, &conn)?
+        .into_iter()
+        .map(|deck| (deck.id, deck))
+        .collect::<HashMap<_, _>>();
+
+    let standings = standings
+        .into_iter()
+        .filter_map(|standing| {
+            decks.get(&standing.deck_id).map(|deck| StandingResponse {
+                rank: standing.rank,
+                deck_id: standing.deck_id,
+                alias: deck.alias.clone(),
+                commander: deck.commander.clone(),
+                elo: standing.elo,
+                games: standing.games,
+                wins: standing.wins,
+            })
+        })
+        .collect();
+
+    Ok(Json(SeasonDetailResponse {
+        season: SeasonResponse::from(season),
+        standings,
+    }))
+}
+
+/// The standings of `season` from the games played in it so far, outside of any league
+pub fn current_standings(
+    season: &Season,
+    conn: &SqliteConnection,
+) -> QueryResult<Vec<NewSeasonStanding>> {
+    let history = Participant::all_between(season.starts_at as f64, season.ends_at as f64, conn)?;
+    Ok(rank_season(season.id, history))
+}
+
+///
+/// Ranks every deck that played in a season by the rating it finished the
+/// season on. `history` holds the season's participations, newest first.
+///
+fn rank_season(season_id: i32, history: Vec<Participant>) -> Vec<NewSeasonStanding> {
+    let mut by_deck: HashMap<i32, NewSeasonStanding> = HashMap::new();
+    for p in history {
+        let standing = by_deck.entry(p.deck_id).or_insert(NewSeasonStanding {
+            season_id,
+            deck_id: p.deck_id,
+            rank: 0,
+            elo: p.elo,
+            games: 0,
+            wins: 0,
+        });
+        standing.games += 1;
+        if p.win {
+            standing.wins += 1;
+        }
+    }
+
+    let mut standings = by_deck.into_iter().map(|(_, s)| s).collect::<Vec<_>>();
+    standings.sort_by(|a, b| {
+        b.elo
+            .partial_cmp(&a.elo)
+            .unwrap_or(Ordering::Equal)
+            .then(a.deck_id.cmp(&b.deck_id))
+    });
+    for (index, standing) in standings.iter_mut().enumerate() {
+        standing.rank = index as i32 + 1;
+    }
+    standings
+}
+
+///
+/// The latest season to start after a deck's previous game at `previous`
+/// and no later than its next one at `next`, if any.
+///
+pub fn season_starting_between(seasons: &[Season], previous: f64, next: f64) -> Option<&Season> {
+    seasons
+        .iter()
+        .filter(|season| previous < season.starts_at as f64 && season.starts_at as f64 <= next)
+        .max_by_key(|season| season.starts_at)
+}
+
+/// Moves `rating` a `reset` part of the way back to the starting rating
+pub fn soft_reset(rating: Rating, reset: f64) -> Rating {
+    let initial = Rating::initial();
+    Rating {
+        elo: rating.elo + (initial.elo - rating.elo) * reset,
+        deviation: rating.deviation + (initial.deviation - rating.deviation) * reset,
+        volatility: rating.volatility,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn season(id: i32, starts_at: i64, ends_at: i64) -> Season {
+        Season {
+            id,
+            name: format!("Season {}", id),
+            starts_at,
+            ends_at,
+            reset: 0.5,
+            closed: false,
+        }
+    }
+
+    fn participant(deck_id: i32, win: bool, elo: f64) -> Participant {
+        Participant {
+            id: 0,
+            game_id: 0,
+            deck_id,
+            win,
+            elo,
+            deviation: 350.0,
+            volatility: 0.06,
+            games_played: 0,
+            previous_elo: 1000.0,
+        }
+    }
+
+    #[test]
+    fn test_finds_the_season_in_between_games() {
+        let seasons = vec![season(1, 100, 200), season(2, 200, 300)];
+        assert_eq!(
+            season_starting_between(&seasons, 50.0, 150.0).map(|s| s.id),
+            Some(1)
+        );
+        assert_eq!(
+            season_starting_between(&seasons, 150.0, 199.0).map(|s| s.id),
+            None
+        );
+        assert_eq!(
+            season_starting_between(&seasons, 150.0, 200.0).map(|s| s.id),
+            Some(2)
+        );
+        assert_eq!(
+            season_starting_between(&seasons, 50.0, 250.0).map(|s| s.id),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_soft_reset_moves_towards_the_start() {
+        let rating = Rating {
+            elo: 1200.0,
+            deviation: 50.0,
+            volatility: 0.05,
+        };
+        assert_eq!(soft_reset(rating, 0.0), rating);
+        assert_eq!(soft_reset(rating, 1.0).elo, Rating::initial().elo);
+        let halfway = soft_reset(rating, 0.5);
+        assert_eq!(halfway.elo, 1100.0);
+        assert_eq!(halfway.deviation, 200.0);
+        assert_eq!(halfway.volatility, 0.05);
+    }
+
+    #[test]
+    fn test_ranks_by_final_rating() {
+        let history = vec![
+            participant(2, true, 1040.0),
+            participant(1, false, 990.0),
+            participant(2, false, 1010.0),
+            participant(1, true, 1020.0),
+            participant(3, true, 1040.0),
+        ];
+        let standings = rank_season(7, history)
+            .into_iter()
+            .map(|s| (s.rank, s.deck_id, s.games, s.wins))
+            .collect::<Vec<_>>();
+        assert_eq!(standings, vec![(1, 2, 2, 1), (2, 3, 1, 1), (3, 1, 2, 1)]);
+    }
+}
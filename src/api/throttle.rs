@@ -0,0 +1,315 @@
+use api::common::current_time;
+use api::error::ApiError;
+use config;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+lazy_static! {
+    ///
+    /// A header set by a reverse proxy in front of the server with the
+    /// address it saw, eg. `X-Forwarded-For` or `X-Real-IP`. Clients can send
+    /// it too, so it is only read when configured.
+    ///
+    pub static ref CLIENT_IP_HEADER: Option<String> =
+        Some(config::env_or("CLIENT_IP_HEADER", String::new())).filter(|name| !name.is_empty());
+}
+
+///
+/// Slows down password guessing. Failed attempts are counted per client IP
+/// and per account, once either goes over its limit further attempts are
+/// refused for a lockout that doubles with every failure. Counts are kept in
+/// memory, so they start over when the server restarts.
+///
+pub struct LoginThrottle {
+    settings: ThrottleSettings,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ThrottleSettings {
+    pub account_attempts: u32,
+    pub ip_attempts: u32,
+    /// Lockout after the first failure over the limit, in seconds
+    pub lockout: i64,
+    pub max_lockout: i64,
+    /// Failures are forgotten once there hasn't been one for this long
+    pub window: i64,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct Failures {
+    count: u32,
+    last: i64,
+}
+
+///
+/// The address the request came from, if it is known. Behind a proxy that is
+/// the one in `CLIENT_IP_HEADER`, otherwise the peer of the connection.
+///
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientIp {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<ClientIp, ()> {
+        let forwarded = CLIENT_IP_HEADER
+            .as_ref()
+            .and_then(|name| request.headers().get_one(name))
+            .and_then(forwarded_ip);
+        let remote = request.remote().map(|address| address.ip());
+        Outcome::Success(ClientIp(forwarded.or(remote)))
+    }
+}
+
+///
+/// Proxies append the address they saw to `X-Forwarded-For`, so the last
+/// one is the only one that can be trusted.
+///
+fn forwarded_ip(value: &str) -> Option<IpAddr> {
+    value.rsplit(',').next()?.trim().parse().ok()
+}
+
+impl ThrottleSettings {
+    pub fn from_env() -> ThrottleSettings {
+        let settings = ThrottleSettings {
+            account_attempts: config::env_or("LOGIN_ACCOUNT_ATTEMPTS", 5),
+            ip_attempts: config::env_or("LOGIN_IP_ATTEMPTS", 20),
+            lockout: config::seconds_or("LOGIN_LOCKOUT", 30),
+            max_lockout: config::seconds_or("LOGIN_MAX_LOCKOUT", 3600),
+            window: config::seconds_or("LOGIN_ATTEMPT_WINDOW", 3600),
+        };
+        // Nobody could ever log in
+        if settings.account_attempts == 0 || settings.ip_attempts == 0 {
+            panic!(
+                "LOGIN_ACCOUNT_ATTEMPTS ({}) and LOGIN_IP_ATTEMPTS ({}) must be at least 1",
+                settings.account_attempts, settings.ip_attempts
+            );
+        }
+        settings
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(settings: ThrottleSettings) -> LoginThrottle {
+        LoginThrottle {
+            settings: settings,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Runs a login attempt for `account` unless it is locked out. An attempt
+    /// fails when it returns `Unauthorized` or `NotFound`, a successful one
+    /// clears the account's failures but not the IP's.
+    ///
+    pub fn attempt<T, F>(&self, ip: &ClientIp, account: &str, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Result<T, ApiError>,
+    {
+        let keys = self.keys("login", ip, account);
+        self.check(&keys, current_time())?;
+
+        let result = f();
+        match result {
+            Ok(_) => self.clear(&keys[0].0),
+            Err(ApiError::Unauthorized) | Err(ApiError::NotFound) => {
+                self.record_failure(&keys, current_time())
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    ///
+    /// Runs a request that sends email to `account`, like a password reset.
+    /// Every request counts towards the limits since they all send mail and
+    /// succeed whether or not the account exists. `kind` keeps the counts
+    /// apart from logins and from other kinds of email.
+    ///
+    pub fn limit<T, F>(&self, kind: &str, ip: &ClientIp, account: &str, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Result<T, ApiError>,
+    {
+        let keys = self.keys(kind, ip, account);
+        let now = current_time();
+        self.check(&keys, now)?;
+        self.record_failure(&keys, now);
+        f()
+    }
+
+    fn keys(&self, kind: &str, ip: &ClientIp, account: &str) -> Vec<(String, u32)> {
+        let mut keys = vec![(
+            format!("{}:account:{}", kind, account.to_lowercase()),
+            self.settings.account_attempts,
+        )];
+        if let Some(address) = ip.0 {
+            keys.push((
+                format!("{}:ip:{}", kind, address),
+                self.settings.ip_attempts,
+            ));
+        }
+        keys
+    }
+
+    fn check(&self, keys: &[(String, u32)], now: i64) -> Result<(), ApiError> {
+        let failures = self.failures.lock().unwrap();
+        let wait = keys
+            .iter()
+            .filter_map(|&(ref key, limit)| {
+                failures
+                    .get(key)
+                    .map(|failures| self.locked_until(failures, limit) - now)
+            })
+            .max()
+            .unwrap_or(0);
+
+        if wait > 0 {
+            Err(ApiError::RateLimited(wait as u64))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn record_failure(&self, keys: &[(String, u32)], now: i64) {
+        let mut failures = self.failures.lock().unwrap();
+        let window = self.settings.window;
+        failures.retain(|_, entry| now - entry.last < window);
+
+        for &(ref key, _) in keys {
+            let entry = failures.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last: now,
+            });
+            entry.count += 1;
+            entry.last = now;
+        }
+    }
+
+    fn clear(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    fn locked_until(&self, failures: &Failures, limit: u32) -> i64 {
+        if failures.count < limit {
+            return failures.last;
+        }
+
+        // Capping the exponent first keeps the shift from overflowing
+        let doublings = cmp::min(failures.count - limit, 32);
+        let lockout = cmp::min(
+            self.settings.lockout.saturating_mul(1 << doublings),
+            self.settings.max_lockout,
+        );
+        failures.last + lockout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn settings() -> ThrottleSettings {
+        ThrottleSettings {
+            account_attempts: 3,
+            ip_attempts: 10,
+            lockout: 30,
+            max_lockout: 300,
+            window: 3600,
+        }
+    }
+
+    #[test]
+    fn test_forwarded_ip() {
+        assert_eq!(
+            forwarded_ip("203.0.113.7"),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            forwarded_ip("10.0.0.1, 2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(forwarded_ip("10.0.0.1, unknown"), None);
+        assert_eq!(forwarded_ip(""), None);
+    }
+
+    fn account_key() -> Vec<(String, u32)> {
+        vec![("account:a@example.com".to_string(), 3)]
+    }
+
+    #[test]
+    fn test_attempts_under_the_limit_are_allowed() {
+        let throttle = LoginThrottle::new(settings());
+        throttle.record_failure(&account_key(), 1000);
+        throttle.record_failure(&account_key(), 1000);
+
+        assert!(throttle.check(&account_key(), 1000).is_ok());
+    }
+
+    #[test]
+    fn test_lockout_doubles_with_every_failure() {
+        let throttle = LoginThrottle::new(settings());
+        for _ in 0..3 {
+            throttle.record_failure(&account_key(), 1000);
+        }
+
+        match throttle.check(&account_key(), 1000) {
+            Err(ApiError::RateLimited(wait)) => assert_eq!(wait, 30),
+            _ => panic!("expected to be rate limited"),
+        }
+        assert!(throttle.check(&account_key(), 1030).is_ok());
+
+        throttle.record_failure(&account_key(), 1030);
+        match throttle.check(&account_key(), 1030) {
+            Err(ApiError::RateLimited(wait)) => assert_eq!(wait, 60),
+            _ => panic!("expected to be rate limited"),
+        }
+    }
+
+    #[test]
+    fn test_lockout_is_capped() {
+        let throttle = LoginThrottle::new(settings());
+        let failures = Failures {
+            count: 200,
+            last: 1000,
+        };
+
+        assert_eq!(throttle.locked_until(&failures, 3), 1300);
+    }
+
+    #[test]
+    fn test_limited_requests_all_count() {
+        let throttle = LoginThrottle::new(settings());
+        let ip = ClientIp(None);
+        for _ in 0..3 {
+            assert!(throttle
+                .limit("password_reset", &ip, "a@example.com", || Ok(()))
+                .is_ok());
+        }
+
+        match throttle.limit("password_reset", &ip, "A@example.com", || Ok(())) {
+            Err(ApiError::RateLimited(_)) => {}
+            _ => panic!("expected to be rate limited"),
+        }
+        // Logins are counted separately
+        assert!(throttle
+            .attempt(&ip, "a@example.com", || Ok::<(), ApiError>(()))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_failures_are_forgotten_after_the_window() {
+        let throttle = LoginThrottle::new(settings());
+        for _ in 0..3 {
+            throttle.record_failure(&account_key(), 1000);
+        }
+        throttle.record_failure(&[("ip:127.0.0.1".to_string(), 10)], 5000);
+
+        assert!(throttle.check(&account_key(), 5000).is_ok());
+        assert_eq!(throttle.failures.lock().unwrap().len(), 1);
+    }
+}
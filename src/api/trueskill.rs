@@ -0,0 +1,294 @@
+use api::elo::{outcome_between, GameOutcome, Rankable};
+use api::rating::{Rating, DEFAULT_DEVIATION};
+use std::f64::consts::{PI, SQRT_2};
+use std::f64::MIN_POSITIVE;
+
+/// Φ⁻¹(0.55), the quantile matching a 10% chance of two decks drawing
+const DRAW_QUANTILE: f64 = 0.125_661_346_855_074;
+const ERFC_COEFFICIENTS: [f64; 10] = [
+    -1.26551223,
+    1.00002368,
+    0.37409196,
+    0.09678418,
+    -0.18628806,
+    0.27886807,
+    -1.13520398,
+    1.48851587,
+    -0.82215223,
+    0.17087277,
+];
+/// Deviations never collapse below this so a deck can always move again
+const MINIMUM_DEVIATION: f64 = 1.0;
+
+///
+/// A TrueSkill style engine. The full factor graph is replaced with the
+/// pairwise approximation: every participant is compared with every other
+/// participant as if they had played a two player game, and the updates
+/// are combined. The mean is stored in `elo` and the standard deviation in
+/// `deviation`. Volatility is not used.
+///
+pub trait TrueSkill
+where
+    Self: Sized,
+{
+    fn compute_trueskill(entities: &Vec<Self>) -> Vec<Self>;
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct TrueSkillParams {
+    /// Performance variance, the skill gap that gives ~76% odds of winning
+    pub beta: f64,
+    /// Dynamics factor added to the deviation before every game
+    pub tau: f64,
+    pub draw_margin: f64,
+}
+
+impl Default for TrueSkillParams {
+    fn default() -> Self {
+        // Same proportions as the reference implementation, scaled to our
+        // starting deviation rather than 25/3
+        let beta = DEFAULT_DEVIATION / 2.0;
+        TrueSkillParams {
+            beta: beta,
+            tau: DEFAULT_DEVIATION / 100.0,
+            draw_margin: DRAW_QUANTILE * SQRT_2 * beta,
+        }
+    }
+}
+
+impl<T> TrueSkill for T
+where
+    T: Rankable,
+{
+    fn compute_trueskill(entities: &Vec<T>) -> Vec<T> {
+        let params = TrueSkillParams::default();
+        let win_count = entities.iter().filter(|entity| entity.get_win()).count();
+
+        entities
+            .iter()
+            .map(|entity| {
+                if win_count == 0 {
+                    return entity.clone_with_rating(entity.get_rating());
+                }
+
+                let results = entities
+                    .iter()
+                    .filter(|opponent| opponent.get_unique_id() != entity.get_unique_id())
+                    .filter_map(|opponent| {
+                        outcome_between(entity.get_win(), opponent.get_win())
+                            .map(|outcome| (opponent.get_rating(), outcome))
+                    })
+                    .collect::<Vec<_>>();
+
+                entity.clone_with_rating(trueskill_rating(entity.get_rating(), &results, &params))
+            })
+            .collect()
+    }
+}
+
+fn trueskill_rating(
+    rating: Rating,
+    results: &[(Rating, GameOutcome)],
+    params: &TrueSkillParams,
+) -> Rating {
+    let variance = rating.deviation.powi(2) + params.tau.powi(2);
+
+    let mut mean = rating.elo;
+    let mut variance_factor = 1.0;
+    for &(opponent, outcome) in results {
+        let opponent_variance = opponent.deviation.powi(2) + params.tau.powi(2);
+        let c = (2.0 * params.beta.powi(2) + variance + opponent_variance).sqrt();
+        let margin = params.draw_margin / c;
+
+        let (v, w) = match outcome {
+            GameOutcome::WIN => {
+                let t = (rating.elo - opponent.elo) / c;
+                (v_win(t, margin), w_win(t, margin))
+            }
+            GameOutcome::LOSE => {
+                let t = (opponent.elo - rating.elo) / c;
+                (-v_win(t, margin), w_win(t, margin))
+            }
+            GameOutcome::DRAW => {
+                let t = (rating.elo - opponent.elo) / c;
+                (v_draw(t, margin), w_draw(t, margin))
+            }
+        };
+
+        mean += variance / c * v;
+        variance_factor *= 1.0 - variance / c.powi(2) * w;
+    }
+
+    Rating {
+        elo: mean,
+        deviation: (variance * variance_factor).sqrt().max(MINIMUM_DEVIATION),
+        volatility: rating.volatility,
+    }
+}
+
+fn v_win(t: f64, margin: f64) -> f64 {
+    let denominator = cdf(t - margin);
+    if denominator < MIN_POSITIVE {
+        return -t + margin;
+    }
+    pdf(t - margin) / denominator
+}
+
+fn w_win(t: f64, margin: f64) -> f64 {
+    let v = v_win(t, margin);
+    v * (v + t - margin)
+}
+
+fn v_draw(t: f64, margin: f64) -> f64 {
+    let denominator = cdf(margin - t) - cdf(-margin - t);
+    if denominator < MIN_POSITIVE {
+        return if t < 0.0 { -t - margin } else { -t + margin };
+    }
+    (pdf(-margin - t) - pdf(margin - t)) / denominator
+}
+
+fn w_draw(t: f64, margin: f64) -> f64 {
+    let denominator = cdf(margin - t) - cdf(-margin - t);
+    if denominator < MIN_POSITIVE {
+        return 1.0;
+    }
+    let v = v_draw(t, margin);
+    v.powi(2) + ((margin - t) * pdf(margin - t) + (margin + t) * pdf(margin + t)) / denominator
+}
+
+fn pdf(x: f64) -> f64 {
+    (-x.powi(2) / 2.0).exp() / (2.0 * PI).sqrt()
+}
+
+fn cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+///
+/// Complementary error function with a fractional error below 1.2e-7,
+/// see Numerical Recipes in C, 2nd edition, section 6.2
+///
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = ERFC_COEFFICIENTS
+        .iter()
+        .rev()
+        .fold(0.0, |acc, coefficient| coefficient + t * acc);
+    let r = t * (-z * z + polynomial).exp();
+
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use models::participant::NewParticipant;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {} but was {}",
+            expected,
+            actual
+        );
+    }
+
+    fn reference_params() -> TrueSkillParams {
+        let beta = 25.0 / 6.0;
+        TrueSkillParams {
+            beta: beta,
+            tau: 25.0 / 300.0,
+            draw_margin: DRAW_QUANTILE * SQRT_2 * beta,
+        }
+    }
+
+    fn reference_rating() -> Rating {
+        Rating {
+            elo: 25.0,
+            deviation: 25.0 / 3.0,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn test_cdf() {
+        assert_close(cdf(0.0), 0.5, 0.0000001);
+        assert_close(cdf(1.0), 0.8413447, 0.0000001);
+        assert_close(cdf(-1.96), 0.0249979, 0.0000001);
+    }
+
+    #[test]
+    fn test_trueskill_rating_matches_reference_1v1() {
+        let params = reference_params();
+
+        let winner = trueskill_rating(
+            reference_rating(),
+            &[(reference_rating(), GameOutcome::WIN)],
+            &params,
+        );
+        let loser = trueskill_rating(
+            reference_rating(),
+            &[(reference_rating(), GameOutcome::LOSE)],
+            &params,
+        );
+
+        assert_close(winner.elo, 29.396, 0.001);
+        assert_close(winner.deviation, 7.171, 0.001);
+        assert_close(loser.elo, 20.604, 0.001);
+        assert_close(loser.deviation, 7.171, 0.001);
+    }
+
+    #[test]
+    fn test_trueskill_rating_matches_reference_draw() {
+        let params = reference_params();
+
+        let result = trueskill_rating(
+            reference_rating(),
+            &[(reference_rating(), GameOutcome::DRAW)],
+            &params,
+        );
+
+        assert_close(result.elo, 25.0, 0.001);
+        assert_close(result.deviation, 6.458, 0.001);
+    }
+
+    #[test]
+    fn test_compute_trueskill_with_4_participants_and_1_winner() {
+        let test_case = vec![
+            NewParticipant::new(0, 1, true, 1000.0),
+            NewParticipant::new(0, 2, false, 1000.0),
+            NewParticipant::new(0, 3, false, 1000.0),
+            NewParticipant::new(0, 4, false, 1000.0),
+        ];
+
+        let result = NewParticipant::compute_trueskill(&test_case);
+
+        assert!(result[0].elo > 1000.0);
+        assert!(result[1].elo < 1000.0);
+        assert_eq!(result[1].elo, result[2].elo);
+        assert_eq!(result[2].elo, result[3].elo);
+        assert!(result[0].deviation < test_case[0].deviation);
+    }
+
+    #[test]
+    fn test_compute_trueskill_with_4_participants_and_0_winners() {
+        let test_case = vec![
+            NewParticipant::new(0, 1, false, 1000.0),
+            NewParticipant::new(0, 2, false, 1000.0),
+            NewParticipant::new(0, 3, false, 1000.0),
+            NewParticipant::new(0, 4, false, 1000.0),
+        ];
+
+        let result = NewParticipant::compute_trueskill(&test_case);
+
+        for (before, after) in test_case.iter().zip(result.iter()) {
+            assert_eq!(before.get_rating(), after.get_rating());
+        }
+    }
+}
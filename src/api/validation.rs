@@ -0,0 +1,108 @@
+use api::error::{ApiError, FieldError};
+use config;
+
+/// The longest address SMTP allows
+const MAX_EMAIL_LENGTH: usize = 254;
+
+lazy_static! {
+    pub static ref GAME_RULES: GameRules = GameRules::from_env();
+}
+
+///
+/// Collects every problem with a request so they can be reported together
+/// instead of one at a time.
+///
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct GameRules {
+    pub min_participants: usize,
+    pub max_participants: usize,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator { errors: vec![] }
+    }
+
+    pub fn check<F: Into<String>, M: Into<String>>(&mut self, valid: bool, field: F, message: M) {
+        if !valid {
+            self.error(field, message);
+        }
+    }
+
+    pub fn error<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(self.errors))
+        }
+    }
+}
+
+impl GameRules {
+    pub fn from_env() -> GameRules {
+        let rules = GameRules {
+            min_participants: config::env_or("GAME_MIN_PARTICIPANTS", 2),
+            max_participants: config::env_or("GAME_MAX_PARTICIPANTS", 8),
+        };
+        // No game could ever be valid
+        if rules.min_participants > rules.max_participants {
+            panic!(
+                "GAME_MIN_PARTICIPANTS ({}) must not be more than GAME_MAX_PARTICIPANTS ({})",
+                rules.min_participants, rules.max_participants
+            );
+        }
+        rules
+    }
+}
+
+///
+/// A loose check that `email` looks like `local@domain.tld`. Deliverability
+/// is proven by verification, this only keeps out values that are clearly
+/// wrong or could smuggle headers into an email.
+///
+pub fn is_email(email: &str) -> bool {
+    let mut parts = email.splitn(2, '@');
+    let (local, domain) = match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => (local, domain),
+        _ => return false,
+    };
+    email.len() <= MAX_EMAIL_LENGTH
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>')
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_is_email() {
+        assert!(is_email("sample@example.com"));
+        assert!(is_email("first.last+tag@mail.example.co.uk"));
+        assert!(!is_email("sample"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("sample@localhost"));
+        assert!(!is_email("sample@example.com."));
+        assert!(!is_email("sample@@example.com"));
+        assert!(!is_email("sample@example.com\nBcc: other@example.com"));
+        assert!(!is_email("../../etc/passwd @example.com"));
+    }
+}
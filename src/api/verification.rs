@@ -0,0 +1,109 @@
+use api::error::ApiError;
+use api::one_time_token;
+use api::throttle::{ClientIp, LoginThrottle};
+use config;
+use db::DbConn;
+use diesel::prelude::*;
+use mailer::{Email, Mailer};
+use models::one_time_token::Purpose;
+use models::player::Player;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+lazy_static! {
+    /// Whether players have to verify their email before they can log in
+    pub static ref REQUIRE_EMAIL_VERIFICATION: bool =
+        config::env_or("REQUIRE_EMAIL_VERIFICATION", false);
+    /// How long a verification token can be used for, in seconds
+    pub static ref EMAIL_VERIFICATION_VALIDITY: i64 =
+        config::seconds_or("EMAIL_VERIFICATION_VALIDITY", 86400);
+    /// Prepended to the verification token in the email, eg. `https://example.com/verify?token=`
+    pub static ref EMAIL_VERIFICATION_URL: String =
+        config::env_or("EMAIL_VERIFICATION_URL", String::new());
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    email: String,
+}
+
+#[post("/verify", format = "application/json", data = "<req>")]
+pub fn verify_email(req: Json<VerifyEmailRequest>, conn: DbConn) -> Result<(), ApiError> {
+    let verify_req = req.into_inner();
+
+    conn.transaction::<_, ApiError, _>(|| {
+        let player_id =
+            one_time_token::redeem(&verify_req.token, Purpose::EmailVerification, &conn)?;
+        let player = Player::find_by_id(player_id, &conn)?;
+        Player::update(
+            Player {
+                verified: true,
+                ..player
+            },
+            &conn,
+        )?;
+        Ok({})
+    })
+}
+
+///
+/// Like `forgot_password` this responds the same whether or not anyone has
+/// the email, and is throttled the same way.
+///
+#[post("/verify/resend", format = "application/json", data = "<req>")]
+pub fn resend_verification(
+    req: Json<ResendVerificationRequest>,
+    conn: DbConn,
+    mailer: State<Box<dyn Mailer>>,
+    throttle: State<LoginThrottle>,
+    ip: ClientIp,
+) -> Result<(), ApiError> {
+    let resend_req = req.into_inner();
+    throttle.limit(
+        "verification",
+        &ip,
+        &resend_req.email,
+        || match Player::find_by_email(&resend_req.email, &conn).optional()? {
+            Some(ref player) if !player.verified => send_verification(player, &**mailer, &conn),
+            _ => Ok({}),
+        },
+    )
+}
+
+///
+/// Emails the player a link to verify their address. Any link sent before
+/// stops working.
+///
+pub fn send_verification(
+    player: &Player,
+    mailer: &dyn Mailer,
+    conn: &SqliteConnection,
+) -> Result<(), ApiError> {
+    let token = one_time_token::issue(
+        player.id,
+        Purpose::EmailVerification,
+        *EMAIL_VERIFICATION_VALIDITY,
+        conn,
+    )?;
+    let email = Email {
+        to: player.email.clone(),
+        subject: "Verify your Hawthorn email".to_string(),
+        body: format!(
+            "Welcome to Hawthorn, {}! Use this within {} hours to verify your email:\n{}{}",
+            player.alias,
+            *EMAIL_VERIFICATION_VALIDITY / 3600,
+            *EMAIL_VERIFICATION_URL,
+            token
+        ),
+    };
+
+    mailer.send(&email).map_err(|message| {
+        error!("{}", message);
+        ApiError::InternalServerError
+    })
+}
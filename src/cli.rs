@@ -0,0 +1,42 @@
+use api::game::recompute_ratings;
+use diesel::sqlite::SqliteConnection;
+use models::player::{Player, Role};
+use models::token::Token;
+
+///
+/// Runs a maintenance command against the database instead of starting
+/// the server, eg. `hawthorn recompute` or `hawthorn set-role <email> admin`
+///
+pub fn run(args: &[String], conn: &SqliteConnection) -> Result<(), String> {
+    match args[0].as_ref() {
+        "recompute" => {
+            let updated = recompute_ratings(conn)
+                .map_err(|e| format!("Failed to recompute ratings: {:?}", e))?;
+            info!("Recomputed ratings, {} participants changed", updated);
+            Ok(())
+        }
+        "set-role" => {
+            if args.len() != 3 {
+                return Err("Usage: set-role <email> <admin|member|read_only>".to_string());
+            }
+            let role = args[2]
+                .parse::<Role>()
+                .map_err(|_| format!("Unknown role: {}", args[2]))?;
+            let player = Player::find_by_email(&args[1], conn)
+                .map_err(|_| format!("No player with email {}", args[1]))?;
+            Token::delete_by_player(player.id, conn)
+                .map_err(|e| format!("Failed to revoke tokens: {:?}", e))?;
+            let player = Player::update(
+                Player {
+                    role: role.as_str().to_string(),
+                    ..player
+                },
+                conn,
+            )
+            .map_err(|e| format!("Failed to update role: {:?}", e))?;
+            info!("{} is now {}", player.email, player.role);
+            Ok(())
+        }
+        command => Err(format!("Unknown command: {}", command)),
+    }
+}
@@ -0,0 +1,25 @@
+use dotenv::dotenv;
+use std::env;
+use std::str::FromStr;
+
+/// Reads an optional setting from the environment, falling back to `default`
+/// when the variable is not set. A value that is present but can't be parsed
+/// is a deployment mistake, so we refuse to start rather than guess.
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    dotenv().ok();
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", key, value)),
+        Err(_) => default,
+    }
+}
+
+/// Reads a number of seconds the same way as `env_or`, which has to be positive
+pub fn seconds_or(key: &str, default: i64) -> i64 {
+    let seconds = env_or(key, default);
+    if seconds <= 0 {
+        panic!("{} ({}) must be positive", key, seconds);
+    }
+    seconds
+}
@@ -0,0 +1,110 @@
+use config;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use time;
+
+/// How much of the recipient goes into the name of a `FileMailer` file
+const MAX_FILE_NAME_ADDRESS: usize = 64;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+///
+/// Sends email to players. Set `MAILER` to pick an implementation, the
+/// default writes every email to a file in `MAILER_DIRECTORY` so tokens
+/// don't end up in the logs.
+///
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), String>;
+}
+
+pub struct StdoutMailer;
+
+/// Writes every email to its own file in `directory`
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+pub fn from_env() -> Box<dyn Mailer> {
+    let kind: String = config::env_or("MAILER", "file".to_string());
+    match kind.as_ref() {
+        "stdout" => Box::new(StdoutMailer),
+        "file" => Box::new(FileMailer {
+            directory: PathBuf::from(config::env_or("MAILER_DIRECTORY", "./mail".to_string())),
+        }),
+        _ => panic!("MAILER has an invalid value: {}", kind),
+    }
+}
+
+impl Email {
+    fn render(&self) -> String {
+        format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            self.to, self.subject, self.body
+        )
+    }
+}
+
+impl Mailer for StdoutMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        println!("{}", email.render());
+        Ok(())
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let now = time::get_time();
+        let path = self.directory.join(format!(
+            "{}-{:09}-{}.eml",
+            now.sec,
+            now.nsec,
+            file_name_safe(&email.to)
+        ));
+
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| File::create(&path))
+            .and_then(|mut file| file.write_all(email.render().as_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+///
+/// The recipient as it can appear in a file name. Anything but letters,
+/// digits and `@.+-_` is replaced so an address can't point outside the
+/// directory, and long addresses are cut short.
+///
+fn file_name_safe(address: &str) -> String {
+    address
+        .chars()
+        .take(MAX_FILE_NAME_ADDRESS)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "@.+-_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_file_name_safe() {
+        assert_eq!(file_name_safe("sample@example.com"), "sample@example.com");
+        assert_eq!(file_name_safe("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(file_name_safe("a\\b c\nd"), "a_b_c_d");
+        assert_eq!(
+            file_name_safe(&"a".repeat(100)).len(),
+            MAX_FILE_NAME_ADDRESS
+        );
+    }
+}
@@ -7,6 +7,8 @@
 )]
 #![allow(proc_macro_derive_resolution_fallback)] // This can be removed after diesel-1.4
 
+extern crate argon2;
+extern crate base64;
 extern crate bcrypt;
 extern crate dotenv;
 extern crate jsonwebtoken;
@@ -14,6 +16,8 @@ extern crate log4rs;
 extern crate rand;
 extern crate rocket_contrib;
 extern crate rocket_cors;
+extern crate serde;
+extern crate sha2;
 extern crate time;
 
 #[macro_use]
@@ -30,7 +34,10 @@ extern crate rocket;
 extern crate serde_derive;
 
 mod api;
+mod cli;
+mod config;
 mod db;
+mod mailer;
 mod models;
 mod schema;
 
@@ -39,6 +46,7 @@ use api::deck::*;
 use api::error::*;
 use api::game::*;
 use api::player::*;
+use api::throttle::{LoginThrottle, ThrottleSettings};
 use db::SqlitePool;
 use dotenv::dotenv;
 use rocket::http::Method;
@@ -51,13 +59,21 @@ fn main() {
     let log_config = env::var("LOG_CONFIG_PATH").expect("LOG_CONFIG_PATH must be set");
     let _ = log4rs::init_file(log_config, Default::default()).unwrap();
     info!("Hawthorn is starting up...");
+    api::load_settings();
 
     let pool = db::init_pool();
     match pool.get() {
         Ok(connection) => {
             info!("Running database migrations...");
             let _ = embedded_migrations::run_with_output(&connection, &mut std::io::stdout());
-            setup_routes(pool);
+
+            let args = env::args().skip(1).collect::<Vec<String>>();
+            if args.is_empty() {
+                setup_routes(pool);
+            } else if let Err(message) = cli::run(&args, &connection) {
+                error!("{}", message);
+                std::process::exit(1);
+            }
         }
         Err(_) => {
             error!("Failed to aquire database connection");
@@ -76,11 +92,55 @@ fn setup_routes(pool: SqlitePool) {
 
     rocket::ignite()
         .manage(pool)
+        .manage(mailer::from_env())
+        .manage(LoginThrottle::new(ThrottleSettings::from_env()))
         .mount("/", routes![api::index::index])
-        .mount("/auth", routes![login, change_password, refresh_token])
+        .mount(
+            "/admin",
+            routes![
+                api::admin::recompute,
+                api::admin::get_invites,
+                api::admin::create_invite,
+                api::admin::delete_invite,
+                api::admin::get_pending_players,
+                api::admin::approve_player,
+                api::admin::reject_player,
+                api::admin::create_season,
+                api::admin::close_season,
+                api::admin::delete_season
+            ],
+        )
+        .mount(
+            "/api-keys",
+            routes![
+                api::api_key::get_api_keys,
+                api::api_key::create_api_key,
+                api::api_key::delete_api_key
+            ],
+        )
+        .mount(
+            "/auth",
+            routes![
+                login,
+                change_password,
+                forgot_password,
+                reset_password,
+                refresh_token,
+                logout,
+                logout_all,
+                api::verification::verify_email,
+                api::verification::resend_verification
+            ],
+        )
         .mount(
             "/players",
-            routes![get_players, get_player, create_player, update_player],
+            routes![
+                get_players,
+                get_player,
+                get_player_leaderboard,
+                create_player,
+                update_player
+            ],
         )
         .mount(
             "/decks",
@@ -89,14 +149,39 @@ fn setup_routes(pool: SqlitePool) {
                 get_deck,
                 create_deck,
                 update_deck,
-                get_leaderboard
+                get_leaderboard,
+                explain_leaderboard
+            ],
+        )
+        .mount(
+            "/commanders",
+            routes![
+                api::commander::get_commanders,
+                api::commander::get_commander
+            ],
+        )
+        .mount(
+            "/seasons",
+            routes![api::season::get_seasons, api::season::get_season],
+        )
+        .mount(
+            "/leagues",
+            routes![
+                api::league::get_leagues,
+                api::league::get_league,
+                api::league::create_league,
+                api::league::add_member,
+                api::league::update_member,
+                api::league::remove_member,
             ],
         )
         .mount(
             "/games",
             routes![get_games, get_game, create_game, delete_game, update_game],
         )
-        .register(catchers![handle_404, handle_401, handle_400,])
+        .register(catchers![
+            handle_400, handle_401, handle_403, handle_404, handle_422, handle_500, handle_503,
+        ])
         .attach(options)
         .launch();
 }
@@ -0,0 +1,118 @@
+use diesel;
+use diesel::prelude::*;
+use models::player::Player;
+use schema::api_key;
+use std::str::FromStr;
+
+///
+/// A long lived credential for bots and scripts. Keys act on behalf of the
+/// player that created them but can only do what their scopes allow.
+///
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "api_key"]
+#[belongs_to(Player)]
+pub struct ApiKey {
+    pub id: i32,
+    pub player_id: i32,
+    pub name: String,
+    pub selector: String,
+    pub verifier: String,
+    /// Comma separated, see `Scope`
+    pub scopes: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "api_key"]
+pub struct NewApiKey {
+    pub player_id: i32,
+    pub name: String,
+    pub selector: String,
+    pub verifier: String,
+    pub scopes: String,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    SubmitGames,
+}
+
+impl ApiKey {
+    pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<ApiKey> {
+        api_key::table.find(id).get_result::<ApiKey>(conn)
+    }
+
+    pub fn find_by_selector(selector: &str, conn: &SqliteConnection) -> QueryResult<ApiKey> {
+        api_key::table
+            .filter(api_key::selector.eq(selector))
+            .first::<ApiKey>(conn)
+    }
+
+    pub fn find_by_player(player_id: i32, conn: &SqliteConnection) -> QueryResult<Vec<ApiKey>> {
+        api_key::table
+            .filter(api_key::player_id.eq(player_id))
+            .order(api_key::id)
+            .load::<ApiKey>(conn)
+    }
+
+    pub fn insert(key: NewApiKey, conn: &SqliteConnection) -> QueryResult<ApiKey> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(api_key::table)
+            .values(&key)
+            .execute(conn)
+            .and_then(|_| api_key::table.order(api_key::id.desc()).first(conn))
+    }
+
+    pub fn touch(id: i32, now: i64, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::update(api_key::table.find(id))
+            .set(api_key::last_used_at.eq(now))
+            .execute(conn)
+    }
+
+    pub fn update_verifier(id: i32, verifier: &str, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::update(api_key::table.find(id))
+            .set(api_key::verifier.eq(verifier))
+            .execute(conn)
+    }
+
+    pub fn delete(id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(api_key::table.find(id)).execute(conn)
+    }
+
+    pub fn delete_by_player(player_id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(api_key::table.filter(api_key::player_id.eq(player_id))).execute(conn)
+    }
+
+    pub fn scopes(&self) -> Vec<Scope> {
+        // Scopes we don't recognise are dropped rather than granted
+        self.scopes
+            .split(',')
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Scope::Read => "read",
+            Scope::SubmitGames => "submit_games",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "submit_games" => Ok(Scope::SubmitGames),
+            _ => Err(()),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use diesel;
 use diesel::prelude::*;
+use models::contains_pattern;
 use models::player::Player;
 use schema::deck;
 
@@ -29,22 +30,47 @@ impl Deck {
         deck::table.order(deck::id).load::<Deck>(conn)
     }
 
+    ///
+    /// Decks whose alias or commander contains `search`, ignoring case, and
+    /// whose active flag matches `active`. Either can be left out.
+    ///
+    pub fn search(
+        search: Option<&str>,
+        active: Option<bool>,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<Deck>> {
+        let mut query = deck::table.order(deck::id).into_boxed();
+        if let Some(search) = search {
+            let pattern = contains_pattern(search);
+            query = query.filter(
+                deck::alias
+                    .like(pattern.clone())
+                    .escape('\\')
+                    .or(deck::commander.like(pattern).escape('\\')),
+            );
+        }
+        if let Some(active) = active {
+            query = query.filter(deck::active.eq(active));
+        }
+        query.load::<Deck>(conn)
+    }
+
     pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<Deck> {
         deck::table.find(id).get_result::<Deck>(conn)
     }
 
+    pub fn find_by_ids(ids: &Vec<i32>, conn: &SqliteConnection) -> QueryResult<Vec<Deck>> {
+        deck::table.filter(deck::id.eq_any(ids)).load::<Deck>(conn)
+    }
+
     pub fn find_by_player(player: &Player, conn: &SqliteConnection) -> QueryResult<Vec<Deck>> {
         Deck::belonging_to(player).load::<Deck>(conn)
     }
 
-    pub fn find_by_players(
-        players: Vec<Player>,
-        conn: &SqliteConnection,
-    ) -> QueryResult<Vec<(Player, Vec<Deck>)>> {
-        let decks = Deck::belonging_to(&players)
-            .load::<Deck>(conn)?
-            .grouped_by(&players);
-        Ok(players.into_iter().zip(decks).collect::<Vec<_>>())
+    pub fn find_by_players(players: &[Player], conn: &SqliteConnection) -> QueryResult<Vec<Deck>> {
+        Deck::belonging_to(players)
+            .order(deck::id)
+            .load::<Deck>(conn)
     }
 
     pub fn update(deck: Deck, conn: &SqliteConnection) -> QueryResult<Deck> {
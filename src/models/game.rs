@@ -1,6 +1,7 @@
 use diesel;
 use diesel::prelude::*;
-use schema::game;
+use diesel::sqlite::Sqlite;
+use schema::{deck, game, participant};
 use time;
 
 #[derive(Identifiable, Queryable, Serialize, Deserialize, Associations, AsChangeset)]
@@ -8,6 +9,8 @@ use time;
 pub struct Game {
     pub id: i32,
     pub time_stamp: f64,
+    /// The league the game was played in, if any
+    pub league_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -15,18 +18,61 @@ pub struct Game {
 #[derive(Deserialize)]
 pub struct NewGame {
     pub time_stamp: f64,
+    pub league_id: Option<i32>,
+}
+
+///
+/// Narrows down which games are listed. Every field that is set has to
+/// match, so combining them gives eg. the games a deck won in a month.
+///
+#[derive(Default, Debug)]
+pub struct GameFilter {
+    pub player_id: Option<i32>,
+    pub deck_id: Option<i32>,
+    pub commander: Option<String>,
+    /// A deck that won the game
+    pub winner: Option<i32>,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    /// Games played outside of any league when not set
+    pub league_id: Option<i32>,
 }
 
 impl Game {
-    pub fn all<T: Into<i32>>(
-        limit: T,
-        offset: T,
+    ///
+    /// The newest games matching `filter`. When `after` is the timestamp and
+    /// id of a game, only games that come after it in that order are returned.
+    ///
+    pub fn filtered(
+        filter: &GameFilter,
+        after: Option<(f64, i32)>,
+        limit: i64,
+        offset: i64,
         conn: &SqliteConnection,
     ) -> QueryResult<Vec<Game>> {
+        let mut query = filtered_query(filter);
+        if let Some((time_stamp, id)) = after {
+            query = query.filter(
+                game::time_stamp
+                    .lt(time_stamp)
+                    .or(game::time_stamp.eq(time_stamp).and(game::id.lt(id))),
+            );
+        }
+
+        query
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<Game>(conn)
+    }
+
+    pub fn count(filter: &GameFilter, conn: &SqliteConnection) -> QueryResult<i64> {
+        filtered_query(filter).count().get_result(conn)
+    }
+
+    pub fn all_chronological(conn: &SqliteConnection) -> QueryResult<Vec<Game>> {
         game::table
-            .order(game::id.desc())
-            .limit(limit.into() as i64)
-            .offset(offset.into() as i64)
+            .order((game::time_stamp, game::id))
             .load::<Game>(conn)
     }
 
@@ -45,27 +91,46 @@ impl Game {
         diesel::delete(game::table.find(id)).execute(conn).is_ok()
     }
 
-    pub fn delete(&self, conn: &SqliteConnection) -> bool {
-        diesel::delete(self).execute(conn).is_ok()
+    pub fn delete(&self, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(self).execute(conn)
     }
 
-    pub fn all_after(self, conn: &SqliteConnection) -> QueryResult<Vec<Game>> {
-        game::table
-            .filter(game::id.gt(self.id))
-            .order(game::id)
-            .load::<Game>(conn)
+    ///
+    /// Games are ordered by when they were played rather than when they were
+    /// recorded, so a backdated game slots in between the existing ones. The
+    /// id only breaks ties between games with the same timestamp. Each league
+    /// keeps its own ratings, so only games from the same league, or from
+    /// outside any league, follow on from each other.
+    ///
+    pub fn all_after(&self, conn: &SqliteConnection) -> QueryResult<Vec<Game>> {
+        let query = game::table
+            .filter(
+                game::time_stamp.gt(self.time_stamp).or(game::time_stamp
+                    .eq(self.time_stamp)
+                    .and(game::id.gt(self.id))),
+            )
+            .into_boxed();
+        let query = match self.league_id {
+            Some(league_id) => query.filter(game::league_id.eq(league_id)),
+            None => query.filter(game::league_id.is_null()),
+        };
+        query.order((game::time_stamp, game::id)).load::<Game>(conn)
     }
 
     pub fn find_previous(&self, conn: &SqliteConnection) -> QueryResult<Game> {
         game::table
-            .filter(game::id.lt(self.id))
-            .order(game::id.desc())
+            .filter(
+                game::time_stamp.lt(self.time_stamp).or(game::time_stamp
+                    .eq(self.time_stamp)
+                    .and(game::id.lt(self.id))),
+            )
+            .order((game::time_stamp.desc(), game::id.desc()))
             .first(conn)
     }
 }
 
 impl NewGame {
-    pub fn new(timestamp: &Option<i32>) -> NewGame {
+    pub fn new(timestamp: &Option<i32>, league_id: Option<i32>) -> NewGame {
         let millis = match timestamp {
             Some(value) => *value as f64,
             None => {
@@ -74,7 +139,10 @@ impl NewGame {
             }
         };
 
-        NewGame { time_stamp: millis }
+        NewGame {
+            time_stamp: millis,
+            league_id,
+        }
     }
 
     pub fn insert(game: NewGame, conn: &SqliteConnection) -> QueryResult<Game> {
@@ -85,3 +153,57 @@ impl NewGame {
             .and_then(|_| game::table.order(game::id.desc()).first(conn))
     }
 }
+
+fn filtered_query<'a>(filter: &'a GameFilter) -> game::BoxedQuery<'a, Sqlite> {
+    let mut query = game::table.into_boxed();
+    if let Some(player_id) = filter.player_id {
+        query = query.filter(
+            game::id.eq_any(
+                participant::table
+                    .inner_join(deck::table)
+                    .filter(deck::player_id.eq(player_id))
+                    .select(participant::game_id),
+            ),
+        );
+    }
+    if let Some(deck_id) = filter.deck_id {
+        query = query.filter(
+            game::id.eq_any(
+                participant::table
+                    .filter(participant::deck_id.eq(deck_id))
+                    .select(participant::game_id),
+            ),
+        );
+    }
+    if let Some(ref commander) = filter.commander {
+        query = query.filter(
+            game::id.eq_any(
+                participant::table
+                    .inner_join(deck::table)
+                    .filter(deck::commander.eq(commander))
+                    .select(participant::game_id),
+            ),
+        );
+    }
+    if let Some(winner) = filter.winner {
+        query = query.filter(
+            game::id.eq_any(
+                participant::table
+                    .filter(participant::deck_id.eq(winner))
+                    .filter(participant::win.eq(true))
+                    .select(participant::game_id),
+            ),
+        );
+    }
+    query = match filter.league_id {
+        Some(league_id) => query.filter(game::league_id.eq(league_id)),
+        None => query.filter(game::league_id.is_null()),
+    };
+    if let Some(from) = filter.from {
+        query = query.filter(game::time_stamp.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(game::time_stamp.le(to));
+    }
+    query
+}
@@ -0,0 +1,74 @@
+use diesel;
+use diesel::prelude::*;
+use schema::invite;
+
+///
+/// A code that lets someone register while `REGISTRATION_MODE` is `invite`.
+/// Every code can be used once.
+///
+#[derive(Identifiable, Queryable)]
+#[table_name = "invite"]
+pub struct Invite {
+    pub id: i32,
+    pub code: String,
+    pub created_by: i32,
+    pub expires_at: i64,
+    pub used_by: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "invite"]
+pub struct NewInvite {
+    pub code: String,
+    pub created_by: i32,
+    pub expires_at: i64,
+}
+
+impl Invite {
+    pub fn all(conn: &SqliteConnection) -> QueryResult<Vec<Invite>> {
+        invite::table.order(invite::id).load::<Invite>(conn)
+    }
+
+    pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<Invite> {
+        invite::table.find(id).get_result::<Invite>(conn)
+    }
+
+    pub fn find_by_code(code: &str, conn: &SqliteConnection) -> QueryResult<Invite> {
+        invite::table
+            .filter(invite::code.eq(code))
+            .first::<Invite>(conn)
+    }
+
+    pub fn insert(invite: NewInvite, conn: &SqliteConnection) -> QueryResult<Invite> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(invite::table)
+            .values(&invite)
+            .execute(conn)
+            .and_then(|_| invite::table.order(invite::id.desc()).first(conn))
+    }
+
+    ///
+    /// Marks the invite used by `player_id` unless it has been used or has
+    /// expired by `now`, in a single statement so two sign ups can't both
+    /// use it. Returns the number of invites changed.
+    ///
+    pub fn redeem(
+        id: i32,
+        player_id: i32,
+        now: i64,
+        conn: &SqliteConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(
+            invite::table
+                .find(id)
+                .filter(invite::used_by.is_null())
+                .filter(invite::expires_at.gt(now)),
+        )
+        .set(invite::used_by.eq(player_id))
+        .execute(conn)
+    }
+
+    pub fn delete(id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(invite::table.find(id)).execute(conn)
+    }
+}
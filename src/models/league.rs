@@ -0,0 +1,174 @@
+use diesel;
+use diesel::prelude::*;
+use schema::{league, league_member};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+///
+/// An independent playgroup. Games recorded in a league are rated against
+/// the league's own history and only its members' decks can take part.
+///
+#[derive(Identifiable, Queryable, Clone, Debug, PartialEq)]
+#[table_name = "league"]
+pub struct League {
+    pub id: i32,
+    pub name: String,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "league"]
+pub struct NewLeague {
+    pub name: String,
+    pub created_at: i64,
+}
+
+#[derive(Identifiable, Queryable, AsChangeset, Associations, Clone, Debug, PartialEq)]
+#[table_name = "league_member"]
+#[belongs_to(League)]
+pub struct LeagueMember {
+    pub id: i32,
+    pub league_id: i32,
+    pub player_id: i32,
+    pub role: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "league_member"]
+pub struct NewLeagueMember {
+    pub league_id: i32,
+    pub player_id: i32,
+    pub role: String,
+}
+
+/// What a member may do within a league, on top of their global role
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LeagueRole {
+    /// Manages the members and the games of the league
+    Admin,
+    Member,
+}
+
+impl League {
+    pub fn all(conn: &SqliteConnection) -> QueryResult<Vec<League>> {
+        league::table.order(league::name).load::<League>(conn)
+    }
+
+    pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<League> {
+        league::table.find(id).get_result::<League>(conn)
+    }
+
+    /// Creates the league with `player_id` as its first admin
+    pub fn insert(
+        league: NewLeague,
+        player_id: i32,
+        conn: &SqliteConnection,
+    ) -> QueryResult<League> {
+        conn.transaction(|| {
+            // Diesel doesn't expose a get result method
+            diesel::insert_into(league::table)
+                .values(&league)
+                .execute(conn)?;
+            let league = league::table
+                .order(league::id.desc())
+                .first::<League>(conn)?;
+            NewLeagueMember::insert(
+                NewLeagueMember {
+                    league_id: league.id,
+                    player_id,
+                    role: LeagueRole::Admin.as_str().to_string(),
+                },
+                conn,
+            )?;
+            Ok(league)
+        })
+    }
+}
+
+impl LeagueMember {
+    pub fn find_by_league_id(
+        league_id: i32,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<LeagueMember>> {
+        league_member::table
+            .filter(league_member::league_id.eq(league_id))
+            .order(league_member::id)
+            .load::<LeagueMember>(conn)
+    }
+
+    /// The ids of every player in the league
+    pub fn player_ids(league_id: i32, conn: &SqliteConnection) -> QueryResult<HashSet<i32>> {
+        let ids = league_member::table
+            .filter(league_member::league_id.eq(league_id))
+            .select(league_member::player_id)
+            .load::<i32>(conn)?;
+        Ok(ids.into_iter().collect())
+    }
+
+    pub fn find(
+        league_id: i32,
+        player_id: i32,
+        conn: &SqliteConnection,
+    ) -> QueryResult<LeagueMember> {
+        league_member::table
+            .filter(league_member::league_id.eq(league_id))
+            .filter(league_member::player_id.eq(player_id))
+            .first::<LeagueMember>(conn)
+    }
+
+    pub fn update(member: LeagueMember, conn: &SqliteConnection) -> QueryResult<LeagueMember> {
+        diesel::update(league_member::table.find(member.id))
+            .set(&member)
+            .execute(conn)
+            .and_then(|_| {
+                league_member::table
+                    .find(member.id)
+                    .get_result::<LeagueMember>(conn)
+            })
+    }
+
+    pub fn delete(&self, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(self).execute(conn)
+    }
+
+    pub fn role(&self) -> LeagueRole {
+        // Anything we don't recognise gets the least privileges
+        self.role.parse().unwrap_or(LeagueRole::Member)
+    }
+}
+
+impl NewLeagueMember {
+    pub fn insert(member: NewLeagueMember, conn: &SqliteConnection) -> QueryResult<LeagueMember> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(league_member::table)
+            .values(&member)
+            .execute(conn)
+            .and_then(|_| {
+                league_member::table
+                    .order(league_member::id.desc())
+                    .first(conn)
+            })
+    }
+}
+
+impl LeagueRole {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LeagueRole::Admin => "admin",
+            LeagueRole::Member => "member",
+        }
+    }
+}
+
+impl FromStr for LeagueRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(LeagueRole::Admin),
+            "member" => Ok(LeagueRole::Member),
+            _ => Err(()),
+        }
+    }
+}
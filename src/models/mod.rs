@@ -1,4 +1,27 @@
+pub mod api_key;
 pub mod deck;
 pub mod game;
+pub mod invite;
+pub mod league;
+pub mod one_time_token;
 pub mod participant;
 pub mod player;
+pub mod refresh_token;
+pub mod season;
+pub mod token;
+
+///
+/// A `LIKE` pattern matching anything that contains `search`. Wildcards in
+/// `search` are escaped with `\`, so queries need `.escape('\\')`.
+///
+pub fn contains_pattern(search: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in search.chars() {
+        if c == '%' || c == '_' || c == '\\' {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
@@ -0,0 +1,93 @@
+use diesel;
+use diesel::prelude::*;
+use models::player::Player;
+use schema::one_time_token;
+
+///
+/// A secret handed to a player out of band, eg. by email, that can be
+/// redeemed exactly once. `selector` is used to look the token up and only
+/// a SHA-256 digest of `verifier` is stored.
+///
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "one_time_token"]
+#[belongs_to(Player)]
+pub struct OneTimeToken {
+    pub id: i32,
+    pub player_id: i32,
+    pub purpose: String,
+    pub selector: String,
+    pub verifier: String,
+    pub expires_at: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "one_time_token"]
+pub struct NewOneTimeToken {
+    pub player_id: i32,
+    pub purpose: String,
+    pub selector: String,
+    pub verifier: String,
+    pub expires_at: i64,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Purpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+impl OneTimeToken {
+    pub fn find_by_selector(
+        selector: &str,
+        purpose: Purpose,
+        conn: &SqliteConnection,
+    ) -> QueryResult<OneTimeToken> {
+        one_time_token::table
+            .filter(one_time_token::selector.eq(selector))
+            .filter(one_time_token::purpose.eq(purpose.as_str()))
+            .first::<OneTimeToken>(conn)
+    }
+
+    pub fn insert(token: NewOneTimeToken, conn: &SqliteConnection) -> QueryResult<OneTimeToken> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(one_time_token::table)
+            .values(&token)
+            .execute(conn)
+            .and_then(|_| {
+                one_time_token::table
+                    .order(one_time_token::id.desc())
+                    .first(conn)
+            })
+    }
+
+    pub fn delete(id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(one_time_token::table.find(id)).execute(conn)
+    }
+
+    pub fn delete_by_player(
+        player_id: i32,
+        purpose: Purpose,
+        conn: &SqliteConnection,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            one_time_token::table
+                .filter(one_time_token::player_id.eq(player_id))
+                .filter(one_time_token::purpose.eq(purpose.as_str())),
+        )
+        .execute(conn)
+    }
+
+    pub fn delete_expired(now: i64, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(one_time_token::table.filter(one_time_token::expires_at.le(now)))
+            .execute(conn)
+    }
+}
+
+impl Purpose {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Purpose::PasswordReset => "password_reset",
+            Purpose::EmailVerification => "email_verification",
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use api::rating::{DEFAULT_DEVIATION, DEFAULT_VOLATILITY};
 use diesel;
 use diesel::prelude::*;
 use models::deck::Deck;
 use models::game::Game;
 use schema::game;
 use schema::participant;
+use std::collections::HashMap;
 
 #[derive(Identifiable, Queryable, Serialize, Deserialize, AsChangeset, Associations, Debug)]
 #[table_name = "participant"]
@@ -15,6 +17,11 @@ pub struct Participant {
     pub deck_id: i32,
     pub win: bool,
     pub elo: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub games_played: i32,
+    /// The rating the deck went into the game with
+    pub previous_elo: f64,
 }
 
 #[derive(Insertable, Debug)]
@@ -24,27 +31,53 @@ pub struct NewParticipant {
     pub deck_id: i32,
     pub win: bool,
     pub elo: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub games_played: i32,
+    /// The rating the deck went into the game with
+    pub previous_elo: f64,
 }
 
 impl Participant {
+    pub fn all(conn: &SqliteConnection) -> QueryResult<Vec<Participant>> {
+        participant::table
+            .order(participant::id)
+            .load::<Participant>(conn)
+    }
+
+    /// The participations of each deck outside of any league, newest first
     pub fn all_grouped_by_deck(
         decks: Vec<Deck>,
         conn: &SqliteConnection,
     ) -> QueryResult<Vec<(Deck, Vec<Participant>)>> {
         let participants = Participant::belonging_to(&decks)
-            .order(participant::id.desc())
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .filter(game::league_id.is_null())
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .select(participant::all_columns)
             .load::<Participant>(conn)?
             .grouped_by(&decks);
         Ok(decks.into_iter().zip(participants).collect::<Vec<_>>())
     }
 
+    ///
+    /// The participations of each deck in `league_id`, or outside of any
+    /// league when it's not set, with their games, newest first.
+    ///
     pub fn all_by_deck_join_game(
         decks: Vec<Deck>,
+        league_id: Option<i32>,
         conn: &SqliteConnection,
     ) -> QueryResult<Vec<(Deck, Vec<(Participant, Game)>)>> {
-        let participants = Participant::belonging_to(&decks)
+        let query = Participant::belonging_to(&decks)
             .inner_join(game::table.on(game::id.eq(participant::game_id)))
-            .order(participant::id.desc())
+            .into_boxed();
+        let query = match league_id {
+            Some(league_id) => query.filter(game::league_id.eq(league_id)),
+            None => query.filter(game::league_id.is_null()),
+        };
+        let participants = query
+            .order((game::time_stamp.desc(), game::id.desc()))
             .load::<(Participant, Game)>(conn)?
             .grouped_by(&decks);
         Ok(decks.into_iter().zip(participants).collect::<Vec<_>>())
@@ -54,15 +87,32 @@ impl Participant {
         Participant::belonging_to(game).load::<Participant>(conn)
     }
 
+    pub fn all_grouped_by_game(
+        games: Vec<Game>,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<(Game, Vec<Participant>)>> {
+        let participants = Participant::belonging_to(&games)
+            .order(participant::id)
+            .load::<Participant>(conn)?
+            .grouped_by(&games);
+        Ok(games.into_iter().zip(participants).collect::<Vec<_>>())
+    }
+
+    /// The participations of `deck` outside of any league, newest first
     pub fn find_by_deck(deck: &Deck, conn: &SqliteConnection) -> QueryResult<Vec<Participant>> {
         Participant::belonging_to(deck)
-            .order(participant::id.desc())
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .filter(game::league_id.is_null())
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .select(participant::all_columns)
             .load::<Participant>(conn)
     }
 
     pub fn find_latest_by_deck(deck: &Deck, conn: &SqliteConnection) -> QueryResult<Participant> {
         Participant::belonging_to(deck)
-            .order(participant::id.desc())
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .select(participant::all_columns)
             .first(conn)
     }
 
@@ -74,38 +124,98 @@ impl Participant {
         Participant::find_latest_by_deck(&deck, conn)
     }
 
-    pub fn find_previous(&self, conn: &SqliteConnection) -> QueryResult<Participant> {
-        participant::table
-            .filter(participant::deck_id.eq(self.deck_id))
-            .filter(participant::game_id.lt(self.game_id))
-            .order(participant::id.desc())
-            .first(conn)
+    pub fn delete_all(
+        participants: Vec<Participant>,
+        conn: &SqliteConnection,
+    ) -> QueryResult<usize> {
+        let ids = participants.into_iter().map(|p| p.id).collect::<Vec<_>>();
+        diesel::delete(participant::table.filter(participant::id.eq_any(ids))).execute(conn)
     }
 
-    pub fn delete_all(participants: Vec<Participant>, conn: &SqliteConnection) {
-        for p in participants {
-            let _ = diesel::delete(participant::table.find(p.id)).execute(conn);
-        }
+    pub fn update(participant: &Participant, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::update(participant::table.find(participant.id))
+            .set(participant)
+            .execute(conn)
     }
 
-    pub fn update_all(parts: &Vec<Participant>, conn: &SqliteConnection) {
-        for p in parts {
-            let _ = diesel::update(participant::table.find(p.id))
-                .set(p)
-                .execute(conn);
-        }
+    ///
+    /// Every participation in a game played after `game` in the same league,
+    /// in the same order as `Game::all_after`.
+    ///
+    pub fn all_after_game(game: &Game, conn: &SqliteConnection) -> QueryResult<Vec<Participant>> {
+        let query = participant::table
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .filter(
+                game::time_stamp.gt(game.time_stamp).or(game::time_stamp
+                    .eq(game.time_stamp)
+                    .and(game::id.gt(game.id))),
+            )
+            .select(participant::all_columns)
+            .into_boxed();
+        let query = match game.league_id {
+            Some(league_id) => query.filter(game::league_id.eq(league_id)),
+            None => query.filter(game::league_id.is_null()),
+        };
+        query
+            .order((game::time_stamp, game::id, participant::id))
+            .load::<Participant>(conn)
     }
 
-    pub fn latest_by_deck_id_before_game(
-        deck_id: i32,
+    ///
+    /// The most recent participation of each deck in `deck_ids` before `game`
+    /// in the same league, along with when it was played. Decks that hadn't
+    /// played there yet are left out.
+    ///
+    pub fn latest_by_deck_ids_before_game(
+        deck_ids: &Vec<i32>,
         game: &Game,
         conn: &SqliteConnection,
-    ) -> QueryResult<Participant> {
+    ) -> QueryResult<HashMap<i32, (Participant, f64)>> {
+        // Games are ordered by when they were played, the id only breaks ties
+        let query = participant::table
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .filter(participant::deck_id.eq_any(deck_ids))
+            .filter(
+                game::time_stamp.lt(game.time_stamp).or(game::time_stamp
+                    .eq(game.time_stamp)
+                    .and(game::id.lt(game.id))),
+            )
+            .select((participant::all_columns, game::time_stamp))
+            .into_boxed();
+        let query = match game.league_id {
+            Some(league_id) => query.filter(game::league_id.eq(league_id)),
+            None => query.filter(game::league_id.is_null()),
+        };
+        let history = query
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .load::<(Participant, f64)>(conn)?;
+
+        let mut latest = HashMap::new();
+        for (participant, time_stamp) in history {
+            latest
+                .entry(participant.deck_id)
+                .or_insert((participant, time_stamp));
+        }
+        Ok(latest)
+    }
+
+    ///
+    /// Every participation in a game played from `from` up to `until` outside
+    /// of any league, newest first
+    ///
+    pub fn all_between(
+        from: f64,
+        until: f64,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<Participant>> {
         participant::table
-            .filter(participant::deck_id.eq(deck_id))
-            .filter(participant::game_id.lt(game.id))
-            .order(participant::id.desc())
-            .first(conn)
+            .inner_join(game::table.on(game::id.eq(participant::game_id)))
+            .filter(game::league_id.is_null())
+            .filter(game::time_stamp.ge(from))
+            .filter(game::time_stamp.lt(until))
+            .order((game::time_stamp.desc(), game::id.desc()))
+            .select(participant::all_columns)
+            .load::<Participant>(conn)
     }
 }
 
@@ -116,6 +226,10 @@ impl NewParticipant {
             game_id,
             win,
             elo,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+            games_played: 0,
+            previous_elo: elo,
         }
     }
 
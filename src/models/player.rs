@@ -1,6 +1,9 @@
 use diesel;
+use diesel::dsl::not;
 use diesel::prelude::*;
-use schema::player;
+use models::contains_pattern;
+use schema::{deck, player};
+use std::str::FromStr;
 
 #[derive(Identifiable, Queryable, Serialize, Deserialize, AsChangeset, Associations)]
 #[table_name = "player"]
@@ -9,6 +12,27 @@ pub struct Player {
     pub alias: String,
     pub email: String,
     pub password: String,
+    pub role: String,
+    pub verified: bool,
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Member,
+    ReadOnly,
+}
+
+/// Where a player's account is in registration, see `REGISTRATION_MODE`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    Active,
+    /// Waiting for an admin to approve it
+    Pending,
+    Rejected,
 }
 
 #[derive(Insertable)]
@@ -18,17 +42,63 @@ pub struct NewPlayer {
     pub alias: String,
     pub email: String,
     pub password: String,
+    pub status: String,
 }
 
 impl Player {
-    pub fn all(conn: &SqliteConnection) -> QueryResult<Vec<Player>> {
-        player::table.order(player::id).load::<Player>(conn)
+    ///
+    /// Players whose alias, or the commander of one of their decks, contains
+    /// `search`. With `active` set, only players who do or don't have an
+    /// active deck.
+    ///
+    pub fn search(
+        search: Option<&str>,
+        active: Option<bool>,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<Player>> {
+        let mut query = player::table.order(player::id).into_boxed();
+        if let Some(search) = search {
+            let pattern = contains_pattern(search);
+            query = query.filter(
+                player::alias
+                    .like(pattern.clone())
+                    .escape('\\')
+                    .or(player::id.eq_any(
+                        deck::table
+                            .filter(deck::commander.like(pattern).escape('\\'))
+                            .select(deck::player_id),
+                    )),
+            );
+        }
+        if let Some(active) = active {
+            let with_active_deck = player::id.eq_any(
+                deck::table
+                    .filter(deck::active.eq(true))
+                    .select(deck::player_id),
+            );
+            if active {
+                query = query.filter(with_active_deck);
+            } else {
+                query = query.filter(not(with_active_deck));
+            }
+        }
+        query.load::<Player>(conn)
     }
 
     pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<Player> {
         player::table.find(id).get_result::<Player>(conn)
     }
 
+    pub fn find_by_status(
+        status: AccountStatus,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<Player>> {
+        player::table
+            .filter(player::status.eq(status.as_str()))
+            .order(player::id)
+            .load::<Player>(conn)
+    }
+
     pub fn find_by_email(email: &String, conn: &SqliteConnection) -> QueryResult<Player> {
         player::table
             .order(player::email)
@@ -53,13 +123,67 @@ impl Player {
         conn: &SqliteConnection,
     ) -> QueryResult<Player> {
         let new_player = Player {
-            id: self.id,
-            alias: self.alias,
-            email: self.email,
             password: password_hash,
+            ..self
         };
         Player::update(new_player, conn)
     }
+
+    pub fn role(&self) -> Role {
+        // Anything we don't recognise gets the least privileges
+        self.role.parse().unwrap_or(Role::ReadOnly)
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        // Anything we don't recognise can't log in
+        self.status.parse().unwrap_or(AccountStatus::Rejected)
+    }
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::ReadOnly => "read_only",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "member" => Ok(Role::Member),
+            "read_only" => Ok(Role::ReadOnly),
+            _ => Err(()),
+        }
+    }
+}
+
+impl AccountStatus {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AccountStatus::Active => "active",
+            AccountStatus::Pending => "pending",
+            AccountStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(AccountStatus::Active),
+            "pending" => Ok(AccountStatus::Pending),
+            "rejected" => Ok(AccountStatus::Rejected),
+            _ => Err(()),
+        }
+    }
 }
 
 impl NewPlayer {
@@ -0,0 +1,72 @@
+use diesel;
+use diesel::prelude::*;
+use models::token::Token;
+use schema::refresh_token;
+
+///
+/// A long lived token that can be exchanged once for a new access token.
+/// Every refresh token issued for a session is kept until it expires, so a
+/// used one being presented again can be recognised as stolen.
+///
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "refresh_token"]
+#[belongs_to(Token)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub token_id: i32,
+    pub selector: String,
+    pub verifier: String,
+    pub used: bool,
+    pub expires_at: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "refresh_token"]
+pub struct NewRefreshToken {
+    pub token_id: i32,
+    pub selector: String,
+    pub verifier: String,
+    pub used: bool,
+    pub expires_at: i64,
+}
+
+impl RefreshToken {
+    pub fn find_by_selector(selector: &str, conn: &SqliteConnection) -> QueryResult<RefreshToken> {
+        refresh_token::table
+            .filter(refresh_token::selector.eq(selector))
+            .first::<RefreshToken>(conn)
+    }
+
+    pub fn insert(token: NewRefreshToken, conn: &SqliteConnection) -> QueryResult<RefreshToken> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(refresh_token::table)
+            .values(&token)
+            .execute(conn)
+            .and_then(|_| {
+                refresh_token::table
+                    .order(refresh_token::id.desc())
+                    .first(conn)
+            })
+    }
+
+    pub fn mark_used(id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::update(refresh_token::table.find(id))
+            .set(refresh_token::used.eq(true))
+            .execute(conn)
+    }
+
+    pub fn update_verifier(id: i32, verifier: &str, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::update(refresh_token::table.find(id))
+            .set(refresh_token::verifier.eq(verifier))
+            .execute(conn)
+    }
+
+    pub fn delete_by_session(token_id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(refresh_token::table.filter(refresh_token::token_id.eq(token_id)))
+            .execute(conn)
+    }
+
+    pub fn delete_expired(now: i64, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(refresh_token::table.filter(refresh_token::expires_at.le(now))).execute(conn)
+    }
+}
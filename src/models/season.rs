@@ -0,0 +1,110 @@
+use diesel;
+use diesel::prelude::*;
+use schema::{season, season_standing};
+
+///
+/// A stretch of time games are grouped into. A game belongs to the season
+/// its timestamp falls in, from `starts_at` up to but not including
+/// `ends_at`. Once closed, its final standings are frozen.
+///
+#[derive(Identifiable, Queryable, AsChangeset, Clone, Debug, PartialEq)]
+#[table_name = "season"]
+pub struct Season {
+    pub id: i32,
+    pub name: String,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    /// How far ratings move back to the starting rating when the season
+    /// starts, from `0` to keep them to `1` for a full reset
+    pub reset: f64,
+    pub closed: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "season"]
+pub struct NewSeason {
+    pub name: String,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub reset: f64,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "season_standing"]
+#[belongs_to(Season)]
+pub struct SeasonStanding {
+    pub id: i32,
+    pub season_id: i32,
+    pub deck_id: i32,
+    pub rank: i32,
+    pub elo: f64,
+    pub games: i32,
+    pub wins: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "season_standing"]
+pub struct NewSeasonStanding {
+    pub season_id: i32,
+    pub deck_id: i32,
+    pub rank: i32,
+    pub elo: f64,
+    pub games: i32,
+    pub wins: i32,
+}
+
+impl Season {
+    pub fn all(conn: &SqliteConnection) -> QueryResult<Vec<Season>> {
+        season::table.order(season::starts_at).load::<Season>(conn)
+    }
+
+    pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<Season> {
+        season::table.find(id).get_result::<Season>(conn)
+    }
+
+    pub fn insert(season: NewSeason, conn: &SqliteConnection) -> QueryResult<Season> {
+        // Diesel doesn't expose a get result method
+        diesel::insert_into(season::table)
+            .values(&season)
+            .execute(conn)
+            .and_then(|_| season::table.order(season::id.desc()).first(conn))
+    }
+
+    ///
+    /// Marks the season closed and stores its final standings in one go,
+    /// so a season is never closed without them.
+    ///
+    pub fn close(
+        &self,
+        standings: &Vec<NewSeasonStanding>,
+        conn: &SqliteConnection,
+    ) -> QueryResult<()> {
+        conn.transaction(|| {
+            diesel::insert_into(season_standing::table)
+                .values(standings)
+                .execute(conn)?;
+            diesel::update(self)
+                .set(season::closed.eq(true))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self, conn: &SqliteConnection) -> QueryResult<usize> {
+        conn.transaction(|| {
+            diesel::delete(SeasonStanding::belonging_to(self)).execute(conn)?;
+            diesel::delete(self).execute(conn)
+        })
+    }
+}
+
+impl SeasonStanding {
+    pub fn find_by_season(
+        season: &Season,
+        conn: &SqliteConnection,
+    ) -> QueryResult<Vec<SeasonStanding>> {
+        SeasonStanding::belonging_to(season)
+            .order(season_standing::rank)
+            .load::<SeasonStanding>(conn)
+    }
+}
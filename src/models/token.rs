@@ -1,47 +1,72 @@
-use db::DbConn;
-use models::{Insertable, Retrievable};
+use diesel;
+use diesel::prelude::*;
+use models::player::Player;
+use schema::token;
 
-#[derive(Identifiable, Queryable, Serialize, Deserialize, Associations, AsChangeset)]
+///
+/// A login session. Access tokens carry the session id from `content` in
+/// their `sid` claim and are only accepted while the session exists, so
+/// deleting it logs the player out.
+///
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Associations)]
 #[table_name = "token"]
+#[belongs_to(Player)]
 pub struct Token {
-    id: i32,
-    player_id: i32,
-    content: String,
+    pub id: i32,
+    pub player_id: i32,
+    pub content: String,
+    pub expires_at: i64,
 }
+
 #[derive(Insertable)]
 #[table_name = "token"]
 pub struct NewToken {
-    player_id: i32,
-    content: String,
+    pub player_id: i32,
+    pub content: String,
+    pub expires_at: i64,
 }
 
-impl Retrievable for Token {
-    fn all(conn: &SqliteConnection) -> QueryResult<Vec<Token>> {
-        token::table.order(token::id).load::<Token>(conn)
-    }
-
-    fn find(id: i32, conn: &SqliteConnection) -> QueryResult<Token> {
+impl Token {
+    pub fn find_by_id(id: i32, conn: &SqliteConnection) -> QueryResult<Token> {
         token::table.find(id).get_result::<Token>(conn)
     }
 
-    fn update(token: Token, conn: &SqliteConnection) -> QueryResult<Token> {
-        diesel::update(token::table.find(token.id))
-            .set(&token)
-            .execute(conn)
-            .and_then(|_| token::table.find(token.id).get_result::<Token>(conn))
+    pub fn find_by_content(content: &str, conn: &SqliteConnection) -> QueryResult<Token> {
+        token::table
+            .filter(token::content.eq(content))
+            .first::<Token>(conn)
     }
 
-    fn delete(id: i32, conn: &SqliteConnection) -> bool {
-        diesel::delete(token::table.find(id)).execute(conn).is_ok()
-    }
-}
-
-impl Insertable for Token {
-    fn insert(token: NewToken, conn: &SqliteConnection) -> QueryResult<Token> {
+    pub fn insert(token: NewToken, conn: &SqliteConnection) -> QueryResult<Token> {
         // Diesel doesn't expose a get result method
         diesel::insert_into(token::table)
             .values(&token)
             .execute(conn)
             .and_then(|_| token::table.order(token::id.desc()).first(conn))
     }
+
+    /// Sessions slide forward every time they are refreshed
+    pub fn extend(id: i32, expires_at: i64, conn: &SqliteConnection) -> QueryResult<Token> {
+        diesel::update(token::table.find(id))
+            .set(token::expires_at.eq(expires_at))
+            .execute(conn)
+            .and_then(|_| token::table.find(id).get_result::<Token>(conn))
+    }
+
+    pub fn delete(id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(token::table.find(id)).execute(conn)
+    }
+
+    pub fn delete_by_content(content: &str, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(token::table.filter(token::content.eq(content))).execute(conn)
+    }
+
+    pub fn delete_by_player(player_id: i32, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(token::table.filter(token::player_id.eq(player_id))).execute(conn)
+    }
+
+    /// Expired sessions can't be refreshed anyway, this only keeps the table small
+    pub fn delete_expired(now: i64, conn: &SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(token::table.filter(token::expires_at.le(now))).execute(conn)
+    }
 }
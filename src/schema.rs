@@ -1,3 +1,16 @@
+table! {
+    api_key (id) {
+        id -> Integer,
+        player_id -> Integer,
+        name -> Text,
+        selector -> Text,
+        verifier -> Text,
+        scopes -> Text,
+        created_at -> BigInt,
+        last_used_at -> Nullable<BigInt>,
+    }
+}
+
 table! {
     deck (id) {
         id -> Integer,
@@ -12,6 +25,45 @@ table! {
     game (id) {
         id -> Integer,
         time_stamp -> Double,
+        league_id -> Nullable<Integer>,
+    }
+}
+
+table! {
+    invite (id) {
+        id -> Integer,
+        code -> Text,
+        created_by -> Integer,
+        expires_at -> BigInt,
+        used_by -> Nullable<Integer>,
+    }
+}
+
+table! {
+    league (id) {
+        id -> Integer,
+        name -> Text,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    league_member (id) {
+        id -> Integer,
+        league_id -> Integer,
+        player_id -> Integer,
+        role -> Text,
+    }
+}
+
+table! {
+    one_time_token (id) {
+        id -> Integer,
+        player_id -> Integer,
+        purpose -> Text,
+        selector -> Text,
+        verifier -> Text,
+        expires_at -> BigInt,
     }
 }
 
@@ -22,6 +74,10 @@ table! {
         deck_id -> Integer,
         win -> Bool,
         elo -> Double,
+        deviation -> Double,
+        volatility -> Double,
+        games_played -> Integer,
+        previous_elo -> Double,
     }
 }
 
@@ -31,6 +87,43 @@ table! {
         alias -> Text,
         email -> Text,
         password -> Text,
+        role -> Text,
+        verified -> Bool,
+        status -> Text,
+    }
+}
+
+table! {
+    refresh_token (id) {
+        id -> Integer,
+        token_id -> Integer,
+        selector -> Text,
+        verifier -> Text,
+        used -> Bool,
+        expires_at -> BigInt,
+    }
+}
+
+table! {
+    season (id) {
+        id -> Integer,
+        name -> Text,
+        starts_at -> BigInt,
+        ends_at -> BigInt,
+        reset -> Double,
+        closed -> Bool,
+    }
+}
+
+table! {
+    season_standing (id) {
+        id -> Integer,
+        season_id -> Integer,
+        deck_id -> Integer,
+        rank -> Integer,
+        elo -> Double,
+        games -> Integer,
+        wins -> Integer,
     }
 }
 
@@ -39,12 +132,32 @@ table! {
         id -> Integer,
         player_id -> Integer,
         content -> Text,
+        expires_at -> BigInt,
     }
 }
 
+joinable!(api_key -> player (player_id));
 joinable!(deck -> player (player_id));
+joinable!(game -> league (league_id));
+joinable!(league_member -> league (league_id));
+joinable!(league_member -> player (player_id));
+joinable!(one_time_token -> player (player_id));
 joinable!(participant -> deck (deck_id));
 joinable!(participant -> game (game_id));
+joinable!(refresh_token -> token (token_id));
+joinable!(season_standing -> deck (deck_id));
+joinable!(season_standing -> season (season_id));
 joinable!(token -> player (player_id));
 
-allow_tables_to_appear_in_same_query!(deck, game, participant, player, token,);
+allow_tables_to_appear_in_same_query!(
+    deck,
+    game,
+    league,
+    league_member,
+    one_time_token,
+    participant,
+    player,
+    season,
+    season_standing,
+    token,
+);